@@ -0,0 +1,113 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use crate::{ACCOUNTS, json_store::JsonStore};
+use poise::serenity_prelude::{User, UserId};
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Serialize, Deserialize)]
+struct Accounts {
+    #[serde(default)]
+    linked: HashMap<u64, String>,
+    /// Users who don't want to be added to or pinged in their PRs' threads.
+    #[serde(default)]
+    muted: HashSet<u64>,
+}
+
+/// Maps Discord user IDs to the GitHub login they verified with `/link-github`.
+pub struct AccountStore(JsonStore<Accounts>);
+
+impl AccountStore {
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        Self(JsonStore::load(path))
+    }
+
+    pub fn github_login(&self, user: UserId) -> Option<String> {
+        self.0.lock().linked.get(&user.get()).cloned()
+    }
+
+    /// GitHub logins are case-insensitive.
+    pub fn discord_user(&self, login: &str) -> Option<UserId> {
+        self.0
+            .lock()
+            .linked
+            .iter()
+            .find(|(_, l)| l.eq_ignore_ascii_case(login))
+            .map(|(id, _)| UserId::new(*id))
+    }
+
+    /// Links `user` to `login`, replacing any previous link of either.
+    pub fn link(&self, user: UserId, login: String) {
+        let mut accounts = self.0.lock();
+        accounts
+            .linked
+            .retain(|_, l| !l.eq_ignore_ascii_case(&login));
+        accounts.linked.insert(user.get(), login);
+        self.0.save(&accounts);
+    }
+
+    pub fn unlink(&self, user: UserId) -> Option<String> {
+        let mut accounts = self.0.lock();
+        let login = accounts.linked.remove(&user.get());
+        if login.is_some() {
+            self.0.save(&accounts);
+        }
+        login
+    }
+
+    pub fn notifications_enabled(&self, user: UserId) -> bool {
+        !self.0.lock().muted.contains(&user.get())
+    }
+
+    pub fn set_notifications(&self, user: UserId, enabled: bool) {
+        let mut accounts = self.0.lock();
+        let changed = if enabled {
+            accounts.muted.remove(&user.get())
+        } else {
+            accounts.muted.insert(user.get())
+        };
+        if changed {
+            self.0.save(&accounts);
+        }
+    }
+}
+
+/// A GitHub user in Discord messages: a mention if they linked their account,
+/// their bold login otherwise.
+pub fn mention(login: &str) -> String {
+    match ACCOUNTS.discord_user(login) {
+        Some(user) => format!("<@{user}>"),
+        None => format!("**{login}**"),
+    }
+}
+
+/// Linked Discord users of these GitHub logins who want to be notified about
+/// their PRs, leaving out whoever caused the notification.
+pub fn to_notify<'a>(
+    logins: impl IntoIterator<Item = &'a str>,
+    actor: Option<&str>,
+) -> Vec<UserId> {
+    let mut users = Vec::new();
+    for login in logins {
+        if actor.is_some_and(|a| a.eq_ignore_ascii_case(login)) {
+            continue;
+        }
+        if let Some(user) = ACCOUNTS.discord_user(login)
+            && ACCOUNTS.notifications_enabled(user)
+            && !users.contains(&user)
+        {
+            users.push(user);
+        }
+    }
+    users
+}
+
+/// A Discord user in GitHub comments and commits, with their GitHub login if linked.
+pub fn attribution(user: &User) -> String {
+    match ACCOUNTS.github_login(user.id) {
+        Some(login) => format!("{} (@{login})", user.name),
+        None => user.name.clone(),
+    }
+}
@@ -0,0 +1,455 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+};
+
+use octocrab::{
+    Octocrab,
+    models::{AuthorAssociation, pulls::PullRequest, pulls::ReviewState},
+};
+use tracing::warn;
+
+use crate::{ENV_VARS, is_contributor};
+
+const CODEOWNERS_PATHS: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// When a PR counts as approved, configured through `APPROVAL_POLICY`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ApprovalPolicy {
+    /// `contributors:N` - N approvals from anyone contributing to the repo.
+    Contributors(usize),
+    /// `maintainers:N` - N approvals from owners, members or collaborators.
+    Maintainers(usize),
+    /// `codeowners` - an approval from a code owner of every touched path.
+    CodeOwners,
+}
+
+impl Default for ApprovalPolicy {
+    fn default() -> Self {
+        Self::Contributors(1)
+    }
+}
+
+impl FromStr for ApprovalPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, count) = match s.split_once(':') {
+            Some((kind, count)) => (kind, Some(count)),
+            None => (s, None),
+        };
+        let parse_count = || {
+            let count = count.unwrap_or("1");
+            count
+                .parse::<usize>()
+                .ok()
+                .filter(|c| *c > 0)
+                .ok_or(format!("invalid approval count {count:?}"))
+        };
+
+        match kind {
+            "contributors" => Ok(Self::Contributors(parse_count()?)),
+            "maintainers" => Ok(Self::Maintainers(parse_count()?)),
+            "codeowners" if count.is_some() => {
+                Err("codeowners doesn't take an approval count".to_string())
+            }
+            "codeowners" => Ok(Self::CodeOwners),
+            _ => Err(format!("unknown approval policy {kind:?}")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ApprovalStatus {
+    pub approvals: usize,
+    pub required: usize,
+}
+
+impl ApprovalStatus {
+    pub fn is_met(&self) -> bool {
+        self.approvals >= self.required
+    }
+}
+
+impl fmt::Display for ApprovalStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{} approvals", self.approvals, self.required)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ReviewerState {
+    pub state: ReviewState,
+    pub association: AuthorAssociation,
+}
+
+/// The latest non-comment review state of every contributor who reviewed the PR,
+/// keyed by their login.
+pub async fn latest_reviews(
+    client: &Octocrab,
+    id: u64,
+) -> Result<HashMap<String, ReviewerState>, octocrab::Error> {
+    let page = client
+        .pulls(&ENV_VARS.repo_owner, &ENV_VARS.repo)
+        .list_reviews(id)
+        .per_page(100)
+        .send()
+        .await?;
+    let reviews = client.all_pages(page).await?;
+
+    let mut latest = HashMap::new();
+    for review in reviews {
+        if !is_contributor(review.author_association.as_ref()) {
+            continue;
+        }
+        let (Some(user), Some(state), Some(association)) =
+            (review.user, review.state, review.author_association)
+        else {
+            continue;
+        };
+        if state != ReviewState::Commented {
+            latest.insert(user.login, ReviewerState { state, association });
+        }
+    }
+
+    Ok(latest)
+}
+
+/// How far a PR is from being approved under the configured policy.
+pub async fn status(
+    client: &Octocrab,
+    pr: &PullRequest,
+    reviews: &HashMap<String, ReviewerState>,
+) -> Result<ApprovalStatus, octocrab::Error> {
+    let approvers = reviews
+        .iter()
+        .filter(|(_, r)| r.state == ReviewState::Approved)
+        .collect::<Vec<_>>();
+
+    let status = match ENV_VARS.approval_policy {
+        ApprovalPolicy::Contributors(required) => ApprovalStatus {
+            approvals: approvers.len(),
+            required,
+        },
+        ApprovalPolicy::Maintainers(required) => ApprovalStatus {
+            approvals: approvers
+                .iter()
+                .filter(|(_, r)| {
+                    matches!(
+                        r.association,
+                        AuthorAssociation::Owner
+                            | AuthorAssociation::Member
+                            | AuthorAssociation::Collaborator
+                    )
+                })
+                .count(),
+            required,
+        },
+        ApprovalPolicy::CodeOwners => {
+            let approvers = approvers
+                .iter()
+                .map(|(login, _)| login.to_lowercase())
+                .collect::<HashSet<_>>();
+            code_owner_status(client, pr, &approvers).await?
+        }
+    };
+    Ok(status)
+}
+
+async fn code_owner_status(
+    client: &Octocrab,
+    pr: &PullRequest,
+    approvers: &HashSet<String>,
+) -> Result<ApprovalStatus, octocrab::Error> {
+    let fallback = ApprovalStatus {
+        approvals: approvers.len(),
+        required: 1,
+    };
+
+    let Some(rules) = fetch_codeowners(client, &pr.base.ref_field).await? else {
+        warn!("No CODEOWNERS file found, falling back to a single approval");
+        return Ok(fallback);
+    };
+
+    let page = client
+        .pulls(&ENV_VARS.repo_owner, &ENV_VARS.repo)
+        .list_files(pr.number)
+        .await?;
+    let files = client.all_pages(page).await?;
+
+    // Every distinct set of owners needs one approval
+    let mut groups = Vec::<Vec<String>>::new();
+    for file in &files {
+        if let Some(owners) = owners_of(&rules, &file.filename)
+            && !owners.is_empty()
+            && !groups.contains(owners)
+        {
+            groups.push(owners.clone());
+        }
+    }
+    if groups.is_empty() {
+        return Ok(fallback);
+    }
+
+    let mut team_members = HashMap::<String, HashSet<String>>::new();
+    let mut approvals = 0;
+    for group in &groups {
+        let mut satisfied = false;
+        for owner in group {
+            let owner = owner.trim_start_matches('@');
+            satisfied = match owner.split_once('/') {
+                Some((org, team)) => {
+                    if !team_members.contains_key(owner) {
+                        let members = fetch_team_members(client, org, team).await;
+                        team_members.insert(owner.to_string(), members);
+                    }
+                    team_members[owner].iter().any(|m| approvers.contains(m))
+                }
+                None => approvers.contains(&owner.to_lowercase()),
+            };
+            if satisfied {
+                break;
+            }
+        }
+        if satisfied {
+            approvals += 1;
+        }
+    }
+
+    Ok(ApprovalStatus {
+        approvals,
+        required: groups.len(),
+    })
+}
+
+async fn fetch_team_members(client: &Octocrab, org: &str, team: &str) -> HashSet<String> {
+    let members = match client.teams(org).members(team).per_page(100).send().await {
+        Ok(page) => client.all_pages(page).await,
+        Err(err) => Err(err),
+    };
+
+    match members {
+        Ok(members) => members
+            .into_iter()
+            .map(|m| m.login.to_lowercase())
+            .collect(),
+        Err(err) => {
+            warn!("Failed fetching members of @{org}/{team}: {err}");
+            HashSet::new()
+        }
+    }
+}
+
+type CodeOwnerRules = Vec<(String, Vec<String>)>;
+
+async fn fetch_codeowners(
+    client: &Octocrab,
+    branch: &str,
+) -> Result<Option<CodeOwnerRules>, octocrab::Error> {
+    let repos = client.repos(&ENV_VARS.repo_owner, &ENV_VARS.repo);
+    for path in CODEOWNERS_PATHS {
+        let content = match repos.get_content().path(path).r#ref(branch).send().await {
+            Ok(content) => content,
+            Err(octocrab::Error::GitHub { source, .. }) if source.status_code.as_u16() == 404 => {
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+
+        let Some(text) = content.items.first().and_then(|c| c.decoded_content()) else {
+            continue;
+        };
+        return Ok(Some(parse_codeowners(&text)));
+    }
+
+    Ok(None)
+}
+
+fn parse_codeowners(text: &str) -> CodeOwnerRules {
+    text.lines()
+        .map(|l| l.split('#').next().unwrap_or_default().trim())
+        .filter(|l| !l.is_empty())
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            let pattern = words.next()?.to_string();
+            let owners = words
+                .filter(|w| w.starts_with('@'))
+                .map(|w| w.to_lowercase())
+                .collect();
+            Some((pattern, owners))
+        })
+        .collect()
+}
+
+/// Owners of a path, the last matching rule wins like on GitHub.
+fn owners_of<'a>(rules: &'a CodeOwnerRules, path: &str) -> Option<&'a Vec<String>> {
+    rules
+        .iter()
+        .rev()
+        .find(|(pattern, _)| pattern_matches(pattern, path))
+        .map(|(_, owners)| owners)
+}
+
+/// Gitignore-style matching as used by CODEOWNERS: patterns without a slash
+/// match at any depth, and matching a directory owns everything below it,
+/// except that a trailing wildcard like `docs/*` only matches direct children.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let anchored = pattern.starts_with('/');
+    let dir_only = pattern.ends_with('/');
+    let pattern = pattern.trim_matches('/');
+    let relative_to_root = anchored || pattern.contains('/');
+
+    let pattern = pattern.split('/').collect::<Vec<_>>();
+    let segments = path.split('/').collect::<Vec<_>>();
+    let last = pattern.last().copied().unwrap_or_default();
+    let owns_children = last == "**" || !last.contains(['*', '?']);
+
+    let starts = if relative_to_root {
+        0..1
+    } else {
+        0..segments.len()
+    };
+    for start in starts {
+        for end in start + 1..=segments.len() {
+            let is_dir = end < segments.len();
+            if (dir_only && !is_dir) || (is_dir && !owns_children) {
+                continue;
+            }
+            if match_segments(&pattern, &segments[start..end]) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+fn match_segments(pattern: &[&str], segments: &[&str]) -> bool {
+    match (pattern.first(), segments.first()) {
+        (None, None) => true,
+        (Some(&"**"), _) => {
+            match_segments(&pattern[1..], segments)
+                || (!segments.is_empty() && match_segments(pattern, &segments[1..]))
+        }
+        (Some(p), Some(s)) => {
+            glob(p.as_bytes(), s.as_bytes()) && match_segments(&pattern[1..], &segments[1..])
+        }
+        _ => false,
+    }
+}
+
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.first(), text.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob(&pattern[1..], text) || (!text.is_empty() && glob(pattern, &text[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob(&pattern[1..], &text[1..]),
+        (Some(p), Some(t)) => p == t && glob(&pattern[1..], &text[1..]),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_policies() {
+        assert_eq!("contributors".parse(), Ok(ApprovalPolicy::Contributors(1)));
+        assert_eq!(
+            "contributors:2".parse(),
+            Ok(ApprovalPolicy::Contributors(2))
+        );
+        assert_eq!("maintainers:3".parse(), Ok(ApprovalPolicy::Maintainers(3)));
+        assert_eq!("codeowners".parse(), Ok(ApprovalPolicy::CodeOwners));
+    }
+
+    #[test]
+    fn rejects_invalid_policies() {
+        assert!("codeowners:2".parse::<ApprovalPolicy>().is_err());
+        assert!("contributors:0".parse::<ApprovalPolicy>().is_err());
+        assert!("maintainers:lots".parse::<ApprovalPolicy>().is_err());
+        assert!("everyone:1".parse::<ApprovalPolicy>().is_err());
+    }
+
+    #[test]
+    fn no_slash_patterns_match_at_any_depth() {
+        assert!(pattern_matches("*", "README.md"));
+        assert!(pattern_matches("*", "src/main.rs"));
+        assert!(pattern_matches("*.rs", "src/webhook/mod.rs"));
+        assert!(!pattern_matches("*.rs", "src/main.rsx"));
+        assert!(pattern_matches("docs", "docs/index.md"));
+        assert!(pattern_matches("docs", "book/docs/index.md"));
+        assert!(pattern_matches("Cargo.toml", "crates/bot/Cargo.toml"));
+    }
+
+    #[test]
+    fn anchored_patterns_match_from_root() {
+        assert!(pattern_matches("/docs", "docs/index.md"));
+        assert!(!pattern_matches("/docs", "book/docs/index.md"));
+        assert!(pattern_matches("/build/logs/", "build/logs/a/b.log"));
+        // A slash in the middle anchors too
+        assert!(pattern_matches("src/webhook", "src/webhook/mod.rs"));
+        assert!(!pattern_matches("src/webhook", "crates/src/webhook/mod.rs"));
+    }
+
+    #[test]
+    fn trailing_wildcard_only_matches_direct_children() {
+        assert!(pattern_matches("docs/*", "docs/index.md"));
+        assert!(!pattern_matches("docs/*", "docs/guide/index.md"));
+        assert!(pattern_matches("src/*.rs", "src/lib.rs"));
+        assert!(!pattern_matches("src/*.rs", "src/webhook/mod.rs"));
+    }
+
+    #[test]
+    fn dir_only_patterns_skip_files() {
+        assert!(pattern_matches("apps/", "apps/web/main.rs"));
+        assert!(pattern_matches("apps/", "crates/apps/main.rs"));
+        assert!(!pattern_matches("apps/", "apps"));
+        assert!(!pattern_matches("apps/", "crates/apps"));
+    }
+
+    #[test]
+    fn double_star_matches_any_number_of_directories() {
+        assert!(pattern_matches("**/logs", "logs/a.log"));
+        assert!(pattern_matches("**/logs", "build/deep/logs/a.log"));
+        assert!(pattern_matches("docs/**", "docs/a/b/c.md"));
+        assert!(pattern_matches("src/**/mod.rs", "src/mod.rs"));
+        assert!(pattern_matches("src/**/mod.rs", "src/a/b/mod.rs"));
+        assert!(!pattern_matches("src/**/mod.rs", "lib/a/mod.rs"));
+    }
+
+    #[test]
+    fn parses_codeowners() {
+        let rules = parse_codeowners(
+            "# Owners\n\n*       @Global-Owner\n/docs/  @org/Docs @writer # docs team\nvendor/\n",
+        );
+        assert_eq!(
+            rules,
+            vec![
+                ("*".to_string(), vec!["@global-owner".to_string()]),
+                (
+                    "/docs/".to_string(),
+                    vec!["@org/docs".to_string(), "@writer".to_string()]
+                ),
+                ("vendor/".to_string(), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn last_matching_rule_wins() {
+        let rules = parse_codeowners("* @global\n/docs/ @docs\nvendor/\n");
+        assert_eq!(
+            owners_of(&rules, "src/main.rs"),
+            Some(&vec!["@global".to_string()])
+        );
+        assert_eq!(
+            owners_of(&rules, "docs/index.md"),
+            Some(&vec!["@docs".to_string()])
+        );
+        assert_eq!(owners_of(&rules, "vendor/lib.rs"), Some(&vec![]));
+        assert_eq!(owners_of(&[].to_vec(), "src/main.rs"), None);
+    }
+}
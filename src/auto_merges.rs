@@ -0,0 +1,86 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use octocrab::params::pulls::MergeMethod;
+use poise::serenity_prelude::UserId;
+use serde::{Deserialize, Serialize};
+
+use crate::json_store::{JsonStore, key, parse_key};
+
+/// Serializable mirror of octocrab's [`MergeMethod`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Method {
+    Merge,
+    Squash,
+    Rebase,
+}
+
+impl From<Method> for MergeMethod {
+    fn from(method: Method) -> Self {
+        match method {
+            Method::Merge => Self::Merge,
+            Method::Squash => Self::Squash,
+            Method::Rebase => Self::Rebase,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingMerge {
+    /// The head the merge was queued for, pushing anything else cancels it.
+    pub sha: String,
+    pub method: Method,
+    pub title: Option<String>,
+    pub requested_by: UserId,
+    /// How the requester is credited in the merge commit message.
+    pub attribution: String,
+}
+
+/// Maps `owner/repo#number` to a merge queued with `/merge auto`, to be done
+/// once the PR is approved and its checks pass.
+pub struct AutoMergeStore(JsonStore<HashMap<String, PendingMerge>>);
+
+impl AutoMergeStore {
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        Self(JsonStore::load(path))
+    }
+
+    pub fn get(&self, repo: &str, id: u64) -> Option<PendingMerge> {
+        self.0.lock().get(&key(repo, id)).cloned()
+    }
+
+    /// PRs with a merge queued.
+    pub fn ids(&self, repo: &str) -> Vec<u64> {
+        self.0
+            .lock()
+            .keys()
+            .filter_map(|key| parse_key(repo, key))
+            .collect()
+    }
+
+    /// PRs with a merge queued for the given head commit.
+    pub fn with_sha(&self, repo: &str, sha: &str) -> Vec<u64> {
+        self.0
+            .lock()
+            .iter()
+            .filter(|(_, merge)| merge.sha == sha)
+            .filter_map(|(key, _)| parse_key(repo, key))
+            .collect()
+    }
+
+    pub fn insert(&self, repo: &str, id: u64, merge: PendingMerge) {
+        let mut merges = self.0.lock();
+        if merges.insert(key(repo, id), merge.clone()) != Some(merge) {
+            self.0.save(&merges);
+        }
+    }
+
+    pub fn remove(&self, repo: &str, id: u64) -> Option<PendingMerge> {
+        let mut merges = self.0.lock();
+        let removed = merges.remove(&key(repo, id));
+        if removed.is_some() {
+            self.0.save(&merges);
+        }
+        removed
+    }
+}
@@ -0,0 +1,40 @@
+use poise::{
+    CreateReply,
+    serenity_prelude::{ContentSafeOptions, Error, content_safe},
+};
+
+use crate::{CmdContext, ENV_VARS, commands::check_relay_role, relay};
+
+/// Post a comment on this thread's pull request on GitHub
+#[poise::command(slash_command, prefix_command, check = "check_relay_role")]
+pub async fn comment(
+    ctx: CmdContext<'_>,
+
+    #[description = "Comment to post on GitHub"]
+    #[rest]
+    text: String,
+) -> Result<(), Error> {
+    if !ENV_VARS.relay_comments {
+        ctx.send(
+            CreateReply::default()
+                .content("Relaying comments to GitHub is disabled.")
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let id = relay::pr_of_channel(ctx.serenity_context(), ctx.channel_id())
+        .await
+        .ok_or(Error::Other("This isn't a pull request thread!"))?;
+
+    // Resolves mentions to names, raw IDs mean nothing on GitHub
+    let text = content_safe(ctx, &text, &ContentSafeOptions::default(), &[]);
+    let content = match relay::post_comment(id, ctx.author(), &text).await {
+        Ok(comment) => format!("Commented on pull request #{id}: <{}>", comment.html_url),
+        Err(err) => format!("Failed commenting on pull request #{id}: {err}"),
+    };
+    ctx.send(CreateReply::default().content(content)).await?;
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use poise::{CreateReply, serenity_prelude::Error};
+
+use crate::{
+    CmdContext, DEAD_LETTERS, QUEUE,
+    commands::check_maintainer,
+    markdown::{self, MAX_MESSAGE_LEN},
+};
+
+/// Serenity errors can include whole response bodies, keep each entry short.
+const MAX_ERROR_LEN: usize = 200;
+
+/// Inspect and replay events whose Discord actions failed after retrying
+#[poise::command(
+    slash_command,
+    prefix_command,
+    hide_in_help,
+    rename = "deadletters",
+    check = "check_maintainer",
+    subcommands("list", "replay")
+)]
+pub async fn dead_letters(_ctx: CmdContext<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// List dead-lettered events
+#[poise::command(slash_command, prefix_command)]
+pub async fn list(ctx: CmdContext<'_>) -> Result<(), Error> {
+    let letters = DEAD_LETTERS.list();
+    if letters.is_empty() {
+        ctx.say("No dead-lettered events.").await?;
+        return Ok(());
+    }
+
+    let response = letters
+        .iter()
+        .map(|l| {
+            format!(
+                "- `{}` {} <t:{}:R>: {}",
+                l.id,
+                l.event.summary(),
+                l.failed_at,
+                markdown::truncate(&l.error.replace('\n', " "), MAX_ERROR_LEN)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    for chunk in markdown::split(&response, MAX_MESSAGE_LEN) {
+        ctx.send(CreateReply::default().content(chunk).ephemeral(true))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Push dead-lettered events back onto the event queue
+#[poise::command(slash_command, prefix_command)]
+pub async fn replay(
+    ctx: CmdContext<'_>,
+    #[description = "Dead letter ID (replays everything if omitted)"] id: Option<u64>,
+) -> Result<(), Error> {
+    let letters = DEAD_LETTERS.take(id);
+    if letters.is_empty() {
+        ctx.say("Nothing to replay.").await?;
+        return Ok(());
+    }
+
+    let mut replayed = 0;
+    for letter in letters {
+        if let Err(err) = QUEUE.push(letter.event.clone()) {
+            DEAD_LETTERS.push(letter.event, format!("Failed replaying: {err}"));
+            continue;
+        }
+        replayed += 1;
+    }
+
+    ctx.say(format!("Replayed {replayed} event(s).")).await?;
+    Ok(())
+}
@@ -20,6 +20,8 @@ fn to_link(path: String, line: Option<u64>) -> String {
     )
 }
 
+// SerenityError is what poise commands fail with, boxing it here wouldn't help
+#[allow(clippy::result_large_err)]
 async fn setup_repo(ctx: &CmdContext<'_>) -> Result<(), SerenityError> {
     if !std::path::Path::new(REPO_PATH).exists() {
         ctx.reply("Git repo needs to be cloned, this may take a moment...")
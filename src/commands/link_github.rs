@@ -0,0 +1,178 @@
+use std::{
+    collections::HashMap,
+    hash::{BuildHasher, RandomState},
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use poise::{
+    CreateReply,
+    serenity_prelude::{Error, UserId},
+};
+use tracing::info;
+
+use crate::{ACCOUNTS, CmdContext, github_client};
+
+/// How long a verification code can be used.
+const CODE_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// Only the most recently updated gists are checked for the code.
+const MAX_CHECKED_GISTS: u8 = 30;
+
+struct PendingLink {
+    login: String,
+    code: String,
+    expires: Instant,
+}
+
+static PENDING: LazyLock<Mutex<HashMap<UserId, PendingLink>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn verification_code(user: UserId) -> String {
+    // RandomState is seeded randomly, which is plenty for a short lived code
+    format!("temper-link-{:016x}", RandomState::new().hash_one(user))
+}
+
+async fn reply(ctx: CmdContext<'_>, content: impl Into<String>) -> Result<(), Error> {
+    ctx.send(CreateReply::default().content(content).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+/// Link your Discord account to your GitHub account
+#[poise::command(
+    slash_command,
+    prefix_command,
+    rename = "link-github",
+    subcommands("start", "verify", "unlink", "status", "notifications")
+)]
+pub async fn link_github(_ctx: CmdContext<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Get a code to prove you own a GitHub account
+#[poise::command(slash_command, prefix_command)]
+pub async fn start(
+    ctx: CmdContext<'_>,
+    #[description = "Your GitHub username"] login: String,
+) -> Result<(), Error> {
+    let login = login.trim().trim_start_matches('@').to_string();
+    let code = verification_code(ctx.author().id);
+    PENDING.lock().unwrap().insert(
+        ctx.author().id,
+        PendingLink {
+            login: login.clone(),
+            code: code.clone(),
+            expires: Instant::now() + CODE_TTL,
+        },
+    );
+
+    reply(
+        ctx,
+        format!(
+            "To prove you own **{login}**, create a public gist on <https://gist.github.com> with the description `{code}`, then run `/link-github verify` within 30 minutes. You can delete the gist afterwards."
+        ),
+    )
+    .await
+}
+
+/// Check for the verification gist and link your accounts
+#[poise::command(slash_command, prefix_command)]
+pub async fn verify(ctx: CmdContext<'_>) -> Result<(), Error> {
+    let user = ctx.author().id;
+    let Some((login, code)) = PENDING
+        .lock()
+        .unwrap()
+        .get(&user)
+        .filter(|p| p.expires > Instant::now())
+        .map(|p| (p.login.clone(), p.code.clone()))
+    else {
+        return reply(ctx, "No pending link, run `/link-github start` first.").await;
+    };
+
+    let gists = github_client()
+        .gists()
+        .list_user_gists(&login)
+        .per_page(MAX_CHECKED_GISTS)
+        .send()
+        .await;
+    let found = match gists {
+        Ok(gists) => gists.items.iter().any(|gist| {
+            gist.public
+                && gist
+                    .description
+                    .as_deref()
+                    .is_some_and(|d| d.contains(&code))
+        }),
+        Err(err) => {
+            return reply(ctx, format!("Failed fetching gists of **{login}**: {err}")).await;
+        }
+    };
+    if !found {
+        return reply(
+            ctx,
+            format!("No public gist of **{login}** has the description `{code}` yet."),
+        )
+        .await;
+    }
+
+    PENDING.lock().unwrap().remove(&user);
+    ACCOUNTS.link(user, login.clone());
+    info!("Linked {} to GitHub account {login}", ctx.author().name);
+    reply(
+        ctx,
+        format!("Linked your account to **{login}** on GitHub."),
+    )
+    .await
+}
+
+/// Remove the link to your GitHub account
+#[poise::command(slash_command, prefix_command)]
+pub async fn unlink(ctx: CmdContext<'_>) -> Result<(), Error> {
+    match ACCOUNTS.unlink(ctx.author().id) {
+        Some(login) => reply(ctx, format!("Unlinked **{login}**.")).await,
+        None => reply(ctx, "Your account isn't linked.").await,
+    }
+}
+
+/// Show which GitHub account yours is linked to
+#[poise::command(slash_command, prefix_command)]
+pub async fn status(ctx: CmdContext<'_>) -> Result<(), Error> {
+    let notifications = if ACCOUNTS.notifications_enabled(ctx.author().id) {
+        "on"
+    } else {
+        "off"
+    };
+    match ACCOUNTS.github_login(ctx.author().id) {
+        Some(login) => {
+            reply(
+                ctx,
+                format!("Linked to **{login}** on GitHub, notifications are {notifications}."),
+            )
+            .await
+        }
+        None => reply(ctx, "Your account isn't linked.").await,
+    }
+}
+
+/// Choose whether you get added to and pinged in your pull requests' threads
+#[poise::command(slash_command, prefix_command)]
+pub async fn notifications(
+    ctx: CmdContext<'_>,
+    #[description = "Whether to be notified"] enabled: bool,
+) -> Result<(), Error> {
+    ACCOUNTS.set_notifications(ctx.author().id, enabled);
+    if enabled {
+        reply(
+            ctx,
+            "You'll be notified about activity on your pull requests.",
+        )
+        .await
+    } else {
+        reply(
+            ctx,
+            "You won't be added to or pinged in pull request threads anymore.",
+        )
+        .await
+    }
+}
@@ -1,7 +1,36 @@
-use octocrab::{OctocrabBuilder, params::pulls::MergeMethod};
-use poise::{CreateReply, serenity_prelude::Error};
+use std::time::Duration;
 
-use crate::{CmdContext, ENV_VARS, pr_discussion::find_pr_from_post};
+use poise::{
+    CreateReply,
+    serenity_prelude::{
+        ButtonStyle, ComponentInteractionCollector, CreateActionRow, CreateButton,
+        CreateInteractionResponse, CreateInteractionResponseMessage, Error,
+    },
+};
+
+use crate::{
+    AUTO_MERGES, CmdContext, ENV_VARS, accounts,
+    auto_merges::{Method, PendingMerge},
+    commands::check_maintainer,
+    github_client,
+    merge_checks::{self, Blocker},
+    relay,
+};
+
+/// How long the confirm/cancel buttons stay usable.
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+
+fn buttons(prefix: &str, confirmable: bool) -> Vec<CreateActionRow> {
+    vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(format!("{prefix}-confirm"))
+            .style(ButtonStyle::Success)
+            .label("Merge")
+            .disabled(!confirmable),
+        CreateButton::new(format!("{prefix}-cancel"))
+            .style(ButtonStyle::Secondary)
+            .label("Cancel"),
+    ])]
+}
 
 #[poise::command(
     slash_command,
@@ -20,21 +49,30 @@ pub async fn merge(
     #[flag]
     rebase: bool,
 
+    #[description = "Merge once the PR is approved and its checks pass"]
+    #[flag]
+    auto: bool,
+
+    #[description = "Merge even if the pre-merge checks fail"]
+    #[flag]
+    force: bool,
+
     #[description = "Commit Message"]
     #[rest]
     msg: Option<String>,
 ) -> Result<(), Error> {
-    let channel = ctx
-        .guild_channel()
-        .await
+    ctx.guild_id()
         .ok_or(Error::Other("Literally why are you doing this in DMs"))?;
-    let id = find_pr_from_post(channel).ok_or(Error::Other("No PR provided!"))?;
+    // Mapping first, so a thread renamed by moderators still merges its PR
+    let id = relay::pr_of_channel(ctx.serenity_context(), ctx.channel_id())
+        .await
+        .ok_or(Error::Other("No PR provided!"))?;
     let method = if squash {
-        MergeMethod::Squash
+        Method::Squash
     } else if rebase {
-        MergeMethod::Rebase
+        Method::Rebase
     } else {
-        MergeMethod::Merge
+        Method::Merge
     };
 
     // Allow having the message in an inline code block
@@ -46,26 +84,123 @@ pub async fn merge(
             .to_string()
     });
 
-    ctx.send(CreateReply::default().content("Attempting to merge..."))
-        .await?;
+    ctx.defer().await?;
+    let client = github_client();
+    let readiness = match merge_checks::check(&client, id).await {
+        Ok(readiness) => readiness,
+        Err(err) => {
+            ctx.send(CreateReply::default().content(format!(
+                "Failed checking pull request #{id} before merging: {err}"
+            )))
+            .await?;
+            return Ok(());
+        }
+    };
 
-    let client = OctocrabBuilder::new()
-        .personal_token(ENV_VARS.github_token.clone())
-        .build()
-        .expect("failed building github client");
+    let mut lines = vec![format!(
+        "Pull request #{id}: **{}**",
+        readiness.pr.title.as_deref().unwrap_or_default()
+    )];
+    if readiness.is_ready() {
+        lines.push("✅ All pre-merge checks passed".to_string());
+    } else {
+        lines.extend(readiness.blockers.iter().map(|b| format!("❌ {b}")));
+        if !force {
+            lines.push("-# Run with `force` to merge anyway".to_string());
+        }
+    }
 
-    let pr = client.pulls(ENV_VARS.repo_owner.to_string(), ENV_VARS.repo.to_string());
-    let merge = pr
-        .merge(id)
-        .message(format!("Merged on Discord by {}", ctx.author().name))
-        .method(method);
+    if auto {
+        if readiness.blockers.contains(&Blocker::Closed) {
+            ctx.say(format!("Pull request #{id} is closed.")).await?;
+        } else if readiness.is_ready() {
+            ctx.say(format!(
+                "Nothing is blocking pull request #{id}, use `/merge` to merge it right away."
+            ))
+            .await?;
+        } else {
+            let pending = PendingMerge {
+                sha: readiness.pr.head.sha.clone(),
+                method,
+                title: msg,
+                requested_by: ctx.author().id,
+                attribution: accounts::attribution(ctx.author()),
+            };
+            AUTO_MERGES.insert(&ENV_VARS.repo_slug(), id, pending);
+            lines.pop_if(|line| line.starts_with("-#"));
+            lines.push(
+                "Queued to merge once nothing blocks it, pushing new commits cancels this."
+                    .to_string(),
+            );
+            ctx.say(lines.join("\n")).await?;
+        }
+        return Ok(());
+    }
 
-    // Jank because title() and the like consume self
-    let merged = if let Some(msg) = msg {
-        merge.title(msg).send().await
+    let summary = lines.join("\n");
+
+    let prefix = ctx.id().to_string();
+    let confirmable = readiness.is_ready() || force;
+    let reply = ctx
+        .send(
+            CreateReply::default()
+                .content(&summary)
+                .components(buttons(&prefix, confirmable)),
+        )
+        .await?;
+
+    let filter_prefix = prefix.clone();
+    let interaction = ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .channel_id(ctx.channel_id())
+        .timeout(CONFIRM_TIMEOUT)
+        .filter(move |mci| mci.data.custom_id.starts_with(&filter_prefix))
+        .await;
+    let Some(interaction) = interaction else {
+        reply
+            .edit(
+                ctx,
+                CreateReply::default()
+                    .content(format!("{summary}\nMerge timed out."))
+                    .components(vec![]),
+            )
+            .await?;
+        return Ok(());
+    };
+
+    let confirmed = interaction.data.custom_id == format!("{prefix}-confirm");
+    let status = if confirmed {
+        "Attempting to merge..."
     } else {
-        merge.send().await
+        "Merge cancelled."
     };
+    interaction
+        .create_response(
+            ctx,
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .content(format!("{summary}\n{status}"))
+                    .components(vec![]),
+            ),
+        )
+        .await?;
+    if !confirmed {
+        return Ok(());
+    }
+
+    // Don't merge commits pushed after the checks above
+    let merged = merge_checks::merge(
+        &client,
+        id,
+        &readiness.pr.head.sha,
+        method.into(),
+        msg,
+        format!(
+            "Merged on Discord by {}",
+            accounts::attribution(ctx.author())
+        ),
+    )
+    .await;
 
     if let Err(err) = merged {
         ctx.send(
@@ -76,10 +211,3 @@ pub async fn merge(
 
     Ok(())
 }
-
-#[allow(dead_code)] // grrr
-async fn check_maintainer(ctx: CmdContext<'_>) -> Result<bool, Error> {
-    ctx.author()
-        .has_role(ctx, ENV_VARS.guild, ENV_VARS.maintainer_role)
-        .await
-}
@@ -1,2 +1,22 @@
+use poise::serenity_prelude::Error;
+
+use crate::{CmdContext, ENV_VARS};
+
+pub mod comment;
+pub mod dead_letters;
 pub mod file_search;
+pub mod link_github;
 pub mod merge;
+
+#[allow(dead_code)] // grrr
+pub(crate) async fn check_maintainer(ctx: CmdContext<'_>) -> Result<bool, Error> {
+    ctx.author()
+        .has_role(ctx, ENV_VARS.guild, ENV_VARS.maintainer_role)
+        .await
+}
+
+pub(crate) async fn check_relay_role(ctx: CmdContext<'_>) -> Result<bool, Error> {
+    ctx.author()
+        .has_role(ctx, ENV_VARS.guild, ENV_VARS.relay_role)
+        .await
+}
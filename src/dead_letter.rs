@@ -0,0 +1,67 @@
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::{Event, json_store::JsonStore};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub id: u64,
+    pub event: Event,
+    pub error: String,
+    pub failed_at: u64,
+}
+
+/// Events whose Discord actions still failed after retrying, persisted as JSON
+/// so they can be inspected and replayed with `/deadletters`.
+pub struct DeadLetterStore(JsonStore<Vec<DeadLetter>>);
+
+impl DeadLetterStore {
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        Self(JsonStore::load(path))
+    }
+
+    pub fn push(&self, event: Event, error: String) {
+        let mut letters = self.0.lock();
+        let id = letters.iter().map(|l| l.id + 1).max().unwrap_or(1);
+        let failed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        error!("Dead-lettered {} (#{id}): {error}", event.summary());
+        letters.push(DeadLetter {
+            id,
+            event,
+            error,
+            failed_at,
+        });
+        self.0.save(&letters);
+    }
+
+    pub fn list(&self) -> Vec<DeadLetter> {
+        self.0.lock().clone()
+    }
+
+    /// Removes and returns a dead letter, or all of them if `id` is `None`.
+    pub fn take(&self, id: Option<u64>) -> Vec<DeadLetter> {
+        let mut letters = self.0.lock();
+        let taken = match id {
+            Some(id) => letters
+                .iter()
+                .position(|l| l.id == id)
+                .map(|pos| vec![letters.remove(pos)])
+                .unwrap_or_default(),
+            None => std::mem::take(&mut *letters),
+        };
+
+        if !taken.is_empty() {
+            self.0.save(&letters);
+        }
+        taken
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+use serde::{Serialize, de::DeserializeOwned};
+use tracing::{error, info, warn};
+
+/// State kept in memory and persisted as a JSON file. Callers lock it, change
+/// what they need and [`save`](Self::save) if anything actually changed.
+pub struct JsonStore<T> {
+    path: PathBuf,
+    data: Mutex<T>,
+}
+
+impl<T: Default + Serialize + DeserializeOwned> JsonStore<T> {
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let data = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|err| {
+                warn!("Failed parsing {}, starting fresh: {err}", path.display());
+                T::default()
+            }),
+            Err(_) => T::default(),
+        };
+
+        Self {
+            path,
+            data: Mutex::new(data),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        self.data.lock().unwrap()
+    }
+
+    pub fn save(&self, data: &T) {
+        let result = serde_json::to_vec(data)
+            .map_err(io::Error::from)
+            .and_then(|data| write_atomic(&self.path, &data));
+        if let Err(err) = result {
+            error!("Failed writing {}: {err}", self.path.display());
+        }
+    }
+}
+
+/// Replaces the file at `path` with `data` through a synced temporary file, so
+/// a crash leaves either the old or the new contents behind, never half of them.
+pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let mut file = File::create(&tmp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+/// `owner/repo#id`, how state about a PR or comment is keyed.
+pub fn key(repo: &str, id: u64) -> String {
+    format!("{repo}#{id}")
+}
+
+/// The ID in a [`key`] of `repo`.
+pub fn parse_key(repo: &str, key: &str) -> Option<u64> {
+    key.strip_prefix(repo)?.strip_prefix('#')?.parse().ok()
+}
+
+/// Where a state file lives: the path in `var` if set, otherwise `file` in
+/// `STATE_DIR` (default `state`). Files older versions kept in the working
+/// directory are moved there.
+pub fn state_path(var: &str, file: &str) -> PathBuf {
+    if let Ok(path) = env::var(var) {
+        return path.into();
+    }
+
+    let dir = PathBuf::from(env::var("STATE_DIR").unwrap_or("state".to_string()));
+    if let Err(err) = fs::create_dir_all(&dir) {
+        error!("Failed creating state directory {}: {err}", dir.display());
+    }
+
+    let path = dir.join(file);
+    let old = Path::new(file);
+    if !path.exists() && old.exists() {
+        match fs::rename(old, &path) {
+            Ok(()) => info!("Moved {file} to {}", path.display()),
+            Err(err) => warn!("Failed moving {file} to {}: {err}", path.display()),
+        }
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_atomic_replaces_contents() {
+        let dir = env::temp_dir().join(format!("json-store-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("store.json");
+
+        write_atomic(&path, b"old").unwrap();
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert!(!dir.join("store.json.tmp").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keys_round_trip() {
+        assert_eq!(key("temper-mc/temper", 42), "temper-mc/temper#42");
+        assert_eq!(
+            parse_key("temper-mc/temper", "temper-mc/temper#42"),
+            Some(42)
+        );
+    }
+
+    #[test]
+    fn keys_of_other_repos_dont_parse() {
+        assert_eq!(
+            parse_key("temper-mc/temper", "temper-mc/temper-web#42"),
+            None
+        );
+        assert_eq!(parse_key("temper-mc/temper", "other/temper#42"), None);
+        assert_eq!(parse_key("temper-mc/temper", "temper-mc/temper#abc"), None);
+    }
+}
@@ -1,37 +1,180 @@
-use std::{
-    env,
-    sync::{LazyLock, OnceLock},
-};
+use std::{collections::HashMap, env, io, sync::LazyLock};
 
-use octocrab::models::pulls::{PullRequest, Review};
+use accounts::AccountStore;
+use approvals::ApprovalPolicy;
+use auto_merges::AutoMergeStore;
+use dead_letter::DeadLetterStore;
+use json_store::state_path;
+use octocrab::{
+    Octocrab, OctocrabBuilder,
+    models::{
+        AuthorAssociation,
+        pulls::{Comment, PullRequest, Review},
+    },
+};
 use poise::serenity_prelude::prelude::SerenityError;
 use poise::serenity_prelude::{ChannelId, ForumTagId, GuildId, RoleId};
-use tokio::sync::{Mutex, mpsc::Sender};
+use queue::EventQueue;
+use review_comments::ReviewCommentStore;
+use serde::{Deserialize, Serialize};
+use status_messages::StatusMessageStore;
+use threads::ThreadStore;
 use tracing::error;
 
+pub mod accounts;
+pub mod approvals;
+pub mod auto_merges;
 pub mod commands;
+pub mod dead_letter;
+pub mod json_store;
+pub mod markdown;
+pub mod merge_checks;
 pub mod pr_discussion;
+pub mod queue;
+pub mod reconcile;
+pub mod relay;
+pub mod retry;
+pub mod review_comments;
+pub mod status_messages;
+pub mod threads;
 pub mod webhook;
 pub type CmdContext<'a> = poise::Context<'a, (), SerenityError>;
 
-pub static TX: OnceLock<Mutex<Sender<Event>>> = OnceLock::new();
+pub static QUEUE: LazyLock<EventQueue> = LazyLock::new(|| {
+    let path = state_path("EVENT_QUEUE_FILE", "events.jsonl");
+    EventQueue::open(&path)
+        .unwrap_or_else(|err| panic!("failed opening event queue {}: {err}", path.display()))
+});
+
+pub static DEAD_LETTERS: LazyLock<DeadLetterStore> =
+    LazyLock::new(|| DeadLetterStore::load(state_path("DEAD_LETTER_FILE", "dead_letters.json")));
+
+pub static THREADS: LazyLock<ThreadStore> =
+    LazyLock::new(|| ThreadStore::load(state_path("THREADS_FILE", "threads.json")));
+
+pub static STATUS_MESSAGES: LazyLock<StatusMessageStore> = LazyLock::new(|| {
+    StatusMessageStore::load(state_path("STATUS_MESSAGES_FILE", "status_messages.json"))
+});
+
+pub static REVIEW_COMMENTS: LazyLock<ReviewCommentStore> = LazyLock::new(|| {
+    ReviewCommentStore::load(state_path("REVIEW_COMMENTS_FILE", "review_comments.json"))
+});
+
+pub static ACCOUNTS: LazyLock<AccountStore> =
+    LazyLock::new(|| AccountStore::load(state_path("ACCOUNTS_FILE", "accounts.json")));
+
+pub static AUTO_MERGES: LazyLock<AutoMergeStore> =
+    LazyLock::new(|| AutoMergeStore::load(state_path("AUTO_MERGES_FILE", "auto_merges.json")));
+
+pub fn github_client() -> Octocrab {
+    let mut builder = OctocrabBuilder::new().personal_token(ENV_VARS.github_token.clone());
+    if let Some(url) = &ENV_VARS.github_api_url {
+        builder = builder
+            .base_uri(url.as_str())
+            .expect("invalid env var GITHUB_API_URL");
+    }
+    builder.build().expect("failed building github client")
+}
 
-pub async fn send_event(event: Event) {
-    let tx = &mut TX.get().unwrap().lock().await;
-    if let Err(err) = tx.send(event).await {
-        error!("Failed sending event: {err}");
+/// Whether reviews by this author count, i.e. they are part of the project.
+pub fn is_contributor(association: Option<&AuthorAssociation>) -> bool {
+    matches!(
+        association.unwrap_or(&AuthorAssociation::None),
+        AuthorAssociation::Collaborator
+            | AuthorAssociation::Contributor
+            | AuthorAssociation::Member
+            | AuthorAssociation::Owner
+    )
+}
+
+pub fn send_event(event: Event) -> io::Result<()> {
+    let result = QUEUE.push(event);
+    if let Err(err) = &result {
+        error!("Failed queueing event: {err}");
     };
+    result
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Event {
     PullRequestOpened(PullRequest),
     PullRequestReady(PullRequest),
-    PullRequestComment(u64, String, String),
+    /// Comment with its body, its author and the PR's author.
+    PullRequestComment(u64, String, String, Option<String>),
+    /// Review comment along with the PR's author.
+    PullRequestReviewComment(u64, Box<Comment>, Option<String>),
+    /// Review thread resolved (`true`) or unresolved, with the ID of its first
+    /// comment and who did it.
+    PullRequestReviewThreadResolved(u64, u64, bool, String),
     PullRequestApproved(PullRequest, Box<Review>),
+    PullRequestChangesRequested(PullRequest, Box<Review>),
+    PullRequestReviewDismissed(PullRequest, Box<Review>),
     PullRequestMerged(PullRequest),
     PullRequestDrafted(PullRequest),
     PullRequestClosed(PullRequest),
+    PullRequestRenamed(PullRequest),
+    PullRequestLabeled(PullRequest),
+    /// Review requested or request removed, with the newly requested reviewer.
+    PullRequestReviewersChanged(PullRequest, Option<String>),
+    /// Commits pushed to the PR branch, with the head SHA before and after.
+    PullRequestPushed(PullRequest, String, String),
+    /// CI checks of a commit changed, with the PRs GitHub associated it with.
+    /// Without any, the PRs are looked up by the commit.
+    ChecksUpdated(String, Vec<u64>),
+}
+
+impl Event {
+    /// The PR an event is about, `None` for events that can concern several.
+    pub fn pr(&self) -> Option<u64> {
+        match self {
+            Self::PullRequestOpened(pr)
+            | Self::PullRequestReady(pr)
+            | Self::PullRequestApproved(pr, _)
+            | Self::PullRequestChangesRequested(pr, _)
+            | Self::PullRequestReviewDismissed(pr, _)
+            | Self::PullRequestMerged(pr)
+            | Self::PullRequestDrafted(pr)
+            | Self::PullRequestClosed(pr)
+            | Self::PullRequestRenamed(pr)
+            | Self::PullRequestLabeled(pr)
+            | Self::PullRequestReviewersChanged(pr, _)
+            | Self::PullRequestPushed(pr, _, _) => Some(pr.number),
+            Self::PullRequestComment(id, ..)
+            | Self::PullRequestReviewComment(id, ..)
+            | Self::PullRequestReviewThreadResolved(id, ..) => Some(*id),
+            Self::ChecksUpdated(..) => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PullRequestOpened(_) => "PullRequestOpened",
+            Self::PullRequestReady(_) => "PullRequestReady",
+            Self::PullRequestComment(..) => "PullRequestComment",
+            Self::PullRequestReviewComment(..) => "PullRequestReviewComment",
+            Self::PullRequestReviewThreadResolved(..) => "PullRequestReviewThreadResolved",
+            Self::PullRequestApproved(..) => "PullRequestApproved",
+            Self::PullRequestChangesRequested(..) => "PullRequestChangesRequested",
+            Self::PullRequestReviewDismissed(..) => "PullRequestReviewDismissed",
+            Self::PullRequestMerged(_) => "PullRequestMerged",
+            Self::PullRequestDrafted(_) => "PullRequestDrafted",
+            Self::PullRequestClosed(_) => "PullRequestClosed",
+            Self::PullRequestRenamed(_) => "PullRequestRenamed",
+            Self::PullRequestLabeled(_) => "PullRequestLabeled",
+            Self::PullRequestReviewersChanged(..) => "PullRequestReviewersChanged",
+            Self::PullRequestPushed(..) => "PullRequestPushed",
+            Self::ChecksUpdated(..) => "ChecksUpdated",
+        }
+    }
+
+    /// Short description for logs, e.g. `PullRequestMerged #42`.
+    pub fn summary(&self) -> String {
+        match (self, self.pr()) {
+            (_, Some(id)) => format!("{} #{id}", self.name()),
+            (Self::ChecksUpdated(sha, _), None) => format!("{} {sha}", self.name()),
+            (_, None) => self.name().to_string(),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -41,14 +184,25 @@ pub struct EnvVars {
     pub tag_draft: ForumTagId,
     pub tag_review_needed: ForumTagId,
     pub tag_approved: ForumTagId,
+    pub tag_changes_requested: ForumTagId,
     pub tag_merged: ForumTagId,
     pub tag_closed: ForumTagId,
     pub member_role: RoleId,
     pub maintainer_role: RoleId,
+    pub archive_closed: bool,
+    pub approval_policy: ApprovalPolicy,
+    /// Lowercased GitHub label names mirrored onto forum tags.
+    pub label_tags: HashMap<String, ForumTagId>,
+    pub relay_comments: bool,
+    pub relay_marker: String,
+    /// Who may relay comments to GitHub, defaults to maintainers.
+    pub relay_role: RoleId,
 
     pub repo_owner: String,
     pub repo: String,
     pub github_token: String,
+    /// Overrides the GitHub API base URL, e.g. to point the bot at a mock server.
+    pub github_api_url: Option<String>,
 }
 
 impl EnvVars {
@@ -59,6 +213,68 @@ impl EnvVars {
             .unwrap_or_else(|_| panic!("invalid env var {name}"))
     }
 
+    fn get_optional(name: &str) -> Option<u64> {
+        let value = env::var(name).ok().filter(|v| !v.is_empty())?;
+        Some(
+            value
+                .parse::<u64>()
+                .unwrap_or_else(|_| panic!("invalid env var {name}")),
+        )
+    }
+
+    /// `RELAY_MARKER`, which mustn't start like markdown (e.g. `>>>` quotes) so
+    /// regular messages aren't published by accident.
+    fn relay_marker() -> String {
+        const DEFAULT: &str = "!gh";
+
+        let Ok(marker) = env::var("RELAY_MARKER") else {
+            return DEFAULT.to_string();
+        };
+        if marker.trim().is_empty() || marker.starts_with(['>', '#', '-', '*', '_', '~', '`', '|'])
+        {
+            error!("RELAY_MARKER {marker:?} looks like markdown, using {DEFAULT:?} instead");
+            return DEFAULT.to_string();
+        }
+        marker
+    }
+
+    /// Parses `LABEL_TAGS`, e.g. `bug=123,performance=456`.
+    fn label_tags() -> HashMap<String, ForumTagId> {
+        let Ok(value) = env::var("LABEL_TAGS") else {
+            return HashMap::new();
+        };
+
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (label, tag) = entry
+                    .rsplit_once('=')
+                    .and_then(|(label, tag)| Some((label.trim(), tag.trim().parse().ok()?)))
+                    .unwrap_or_else(|| panic!("invalid env var LABEL_TAGS entry {entry:?}"));
+                (label.to_lowercase(), ForumTagId::new(tag))
+            })
+            .collect()
+    }
+
+    /// The tags reflecting PR state, of which a forum post has exactly one.
+    pub fn state_tags(&self) -> [ForumTagId; 6] {
+        [
+            self.tag_draft,
+            self.tag_review_needed,
+            self.tag_approved,
+            self.tag_changes_requested,
+            self.tag_merged,
+            self.tag_closed,
+        ]
+    }
+
+    /// `owner/repo` of the tracked repository.
+    pub fn repo_slug(&self) -> String {
+        format!("{}/{}", self.repo_owner, self.repo)
+    }
+
     fn new() -> Self {
         Self {
             guild: GuildId::new(Self::get("GUILD")),
@@ -66,14 +282,33 @@ impl EnvVars {
             tag_draft: ForumTagId::new(Self::get("FORUM_TAG_DRAFT")),
             tag_review_needed: ForumTagId::new(Self::get("FORUM_TAG_REVIEW_NEEDED")),
             tag_approved: ForumTagId::new(Self::get("FORUM_TAG_APPROVED")),
+            // Added later, so older setups without it keep working
+            tag_changes_requested: ForumTagId::new(
+                Self::get_optional("FORUM_TAG_CHANGES_REQUESTED")
+                    .unwrap_or_else(|| Self::get("FORUM_TAG_REVIEW_NEEDED")),
+            ),
             tag_merged: ForumTagId::new(Self::get("FORUM_TAG_MERGED")),
             tag_closed: ForumTagId::new(Self::get("FORUM_TAG_CLOSED")),
             member_role: RoleId::new(Self::get("MEMBER_ROLE")),
             maintainer_role: RoleId::new(Self::get("MAINTAINER_ROLE")),
+            archive_closed: env::var("ARCHIVE_CLOSED_THREADS").is_ok_and(|v| v == "true"),
+            approval_policy: env::var("APPROVAL_POLICY")
+                .map(|v| {
+                    v.parse()
+                        .unwrap_or_else(|err| panic!("invalid env var APPROVAL_POLICY: {err}"))
+                })
+                .unwrap_or_default(),
+            label_tags: Self::label_tags(),
+            relay_comments: env::var("RELAY_COMMENTS").is_ok_and(|v| v == "true"),
+            relay_marker: Self::relay_marker(),
+            relay_role: RoleId::new(
+                Self::get_optional("RELAY_ROLE").unwrap_or_else(|| Self::get("MAINTAINER_ROLE")),
+            ),
 
             repo_owner: env::var("REPO_OWNER").expect("missing env var REPO_OWNER"),
             repo: env::var("REPO").expect("missing env var REPO"),
             github_token: env::var("GITHUB_TOKEN").expect("missing env var GITHUB_TOKEN"),
+            github_api_url: env::var("GITHUB_API_URL").ok().filter(|v| !v.is_empty()),
         }
     }
 }
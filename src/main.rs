@@ -4,22 +4,24 @@ use std::{
         Arc,
         atomic::{AtomicBool, Ordering},
     },
-    time::Duration,
 };
 
-use bot::{ENV_VARS, Event, TX, pr_discussion, webhook::setup_webhook};
+use bot::{
+    AUTO_MERGES, DEAD_LETTERS, ENV_VARS, Event, QUEUE, accounts, approvals, github_client,
+    markdown,
+    pr_discussion::{self, DiscussionError},
+    reconcile, relay,
+    webhook::setup_webhook,
+};
+use octocrab::models::pulls::PullRequest;
 use poise::{
     Framework, FrameworkOptions, Prefix, PrefixFrameworkOptions,
     serenity_prelude::{
         ClientBuilder, Context, CreateMessage, Error, EventHandler, GatewayIntents, GuildId,
-        Member, async_trait,
+        Member, Message, async_trait,
     },
 };
-use tokio::sync::{
-    Mutex,
-    mpsc::{Receiver, channel},
-};
-use tracing::{error, warn};
+use tracing::{error, info, warn};
 pub struct MainLoop {
     main_loop_running: AtomicBool,
 }
@@ -32,80 +34,191 @@ impl Default for MainLoop {
     }
 }
 
-async fn run_main_loop(ctx: &Arc<Context>, rx: &mut Receiver<Event>) {
-    while let Some(event) = rx.recv().await {
-        match event {
-            Event::PullRequestOpened(pr) => pr_discussion::pr_created(ctx, pr).await,
-            Event::PullRequestReady(pr) => {
-                pr_discussion::apply_tag(ctx, pr.number, ENV_VARS.tag_review_needed).await;
-                pr_discussion::send_message(
-                    ctx,
-                    pr.number,
-                    CreateMessage::new()
-                        .content(format!("Pull request #{} **ready for review**!", pr.number)),
-                )
-                .await
-            }
-            Event::PullRequestApproved(pr, review) => {
-                pr_discussion::apply_tag(ctx, pr.number, ENV_VARS.tag_approved).await;
-                let user = review
-                    .user
-                    .map(|u| u.login)
-                    .unwrap_or("unknown".to_string());
-                pr_discussion::send_message(
-                    ctx,
-                    pr.number,
-                    CreateMessage::new().content(format!(
-                        "Pull request #{} was approved by **{}**!",
-                        pr.number, user
-                    )),
+/// Review bodies are cut off after this many characters.
+const MAX_REVIEW_SUMMARY_LEN: usize = 1500;
+
+async fn run_main_loop(ctx: &Arc<Context>) {
+    loop {
+        let (seq, event) = QUEUE.next().await;
+        if let Err(err) = handle_event(ctx, event.clone()).await {
+            DEAD_LETTERS.push(event, err.to_string());
+        }
+        QUEUE.ack(seq);
+    }
+}
+
+fn author(pr: &PullRequest) -> Option<&str> {
+    pr.user.as_ref().map(|u| u.login.as_str())
+}
+
+async fn handle_event(ctx: &Arc<Context>, event: Event) -> Result<(), DiscussionError> {
+    match event {
+        Event::PullRequestOpened(pr) => pr_discussion::pr_created(ctx, pr).await?,
+        Event::PullRequestReady(pr) => {
+            pr_discussion::apply_tag(ctx, pr.number, ENV_VARS.tag_review_needed).await?;
+            let reviewers = pr
+                .requested_reviewers
+                .iter()
+                .flatten()
+                .map(|u| u.login.as_str());
+            let pings = accounts::to_notify(reviewers, author(&pr));
+            pr_discussion::send_relayed(
+                ctx,
+                pr.number,
+                &format!("Pull request #{} **ready for review**!", pr.number),
+                None,
+                &pings,
+            )
+            .await?;
+        }
+        Event::PullRequestApproved(pr, review) => {
+            let client = github_client();
+            let reviews = approvals::latest_reviews(&client, pr.number).await?;
+            let status = approvals::status(&client, &pr, &reviews).await?;
+            let tag = reconcile::expected_tag(&client, &pr).await?;
+            pr_discussion::apply_tag(ctx, pr.number, tag).await?;
+            pr_discussion::update_reviewers(ctx, &pr).await?;
+
+            let user = review
+                .user
+                .map(|u| u.login)
+                .unwrap_or("unknown".to_string());
+            let content = if status.is_met() {
+                format!("Pull request #{} was approved by **{}**!", pr.number, user)
+            } else {
+                format!(
+                    "Pull request #{} was approved by **{}** ({status})",
+                    pr.number, user
                 )
-                .await
+            };
+            let pings = accounts::to_notify(author(&pr), Some(&user));
+            pr_discussion::send_relayed(ctx, pr.number, &content, None, &pings).await?;
+            pr_discussion::try_auto_merge(ctx, pr.number).await?;
+        }
+        Event::PullRequestChangesRequested(pr, review) => {
+            pr_discussion::apply_tag(ctx, pr.number, ENV_VARS.tag_changes_requested).await?;
+            pr_discussion::update_reviewers(ctx, &pr).await?;
+            let user = review
+                .user
+                .as_ref()
+                .map(|u| u.login.clone())
+                .unwrap_or("unknown".to_string());
+            let mut content = format!(
+                "{} [requested changes](<{}>) on pull request #{}",
+                accounts::mention(&user),
+                review.html_url,
+                pr.number
+            );
+            if let Some(body) = review.body.as_deref().filter(|b| !b.trim().is_empty()) {
+                let summary =
+                    markdown::truncate(&markdown::github_to_discord(body), MAX_REVIEW_SUMMARY_LEN);
+                content.push_str(":\n");
+                content.push_str(&markdown::quote(&summary));
             }
-            Event::PullRequestMerged(pr) => {
-                pr_discussion::apply_tag(ctx, pr.number, ENV_VARS.tag_merged).await;
-                let user = pr
-                    .merged_by
-                    .map(|u| u.login)
-                    .unwrap_or("unknown".to_string());
-                pr_discussion::send_message(
-                    ctx,
-                    pr.number,
-                    CreateMessage::new().content(format!(
-                        "Pull request #{} was merged by **{}** :tada:!",
-                        pr.number, user
-                    )),
-                )
-                .await;
+            let pings = accounts::to_notify(author(&pr), Some(&user));
+            pr_discussion::send_relayed(ctx, pr.number, &content, None, &pings).await?;
+        }
+        Event::PullRequestReviewDismissed(pr, review) => {
+            let tag = reconcile::expected_tag(&github_client(), &pr).await?;
+            pr_discussion::apply_tag(ctx, pr.number, tag).await?;
+            pr_discussion::update_reviewers(ctx, &pr).await?;
+            let user = review
+                .user
+                .map(|u| u.login)
+                .unwrap_or("unknown".to_string());
+            let pings = accounts::to_notify(author(&pr), None);
+            pr_discussion::send_relayed(
+                ctx,
+                pr.number,
+                &format!(
+                    "Review by **{}** on pull request #{} was dismissed",
+                    user, pr.number
+                ),
+                None,
+                &pings,
+            )
+            .await?;
+            // The dismissed review may have been the only one requesting changes
+            pr_discussion::try_auto_merge(ctx, pr.number).await?;
+        }
+        Event::PullRequestMerged(pr) => {
+            pr_discussion::apply_tag(ctx, pr.number, ENV_VARS.tag_merged).await?;
+            AUTO_MERGES.remove(&ENV_VARS.repo_slug(), pr.number);
+            let user = pr
+                .merged_by
+                .map(|u| u.login)
+                .unwrap_or("unknown".to_string());
+            pr_discussion::send_message(
+                ctx,
+                pr.number,
+                CreateMessage::new().content(format!(
+                    "Pull request #{} was merged by **{}** :tada:!",
+                    pr.number, user
+                )),
+            )
+            .await?;
+            if ENV_VARS.archive_closed {
+                pr_discussion::archive(ctx, pr.number).await?;
             }
-            Event::PullRequestDrafted(pr) => {
-                pr_discussion::apply_tag(ctx, pr.number, ENV_VARS.tag_draft).await
+        }
+        Event::PullRequestDrafted(pr) => {
+            pr_discussion::apply_tag(ctx, pr.number, ENV_VARS.tag_draft).await?;
+        }
+        Event::PullRequestClosed(pr) => {
+            pr_discussion::apply_tag(ctx, pr.number, ENV_VARS.tag_closed).await?;
+            pr_discussion::cancel_auto_merge(ctx, pr.number, "the pull request was closed").await?;
+            pr_discussion::send_message(
+                ctx,
+                pr.number,
+                CreateMessage::new().content(format!("Pull request #{} was closed!", pr.number)),
+            )
+            .await?;
+            if ENV_VARS.archive_closed {
+                pr_discussion::archive(ctx, pr.number).await?;
             }
-            Event::PullRequestClosed(pr) => {
-                pr_discussion::apply_tag(ctx, pr.number, ENV_VARS.tag_closed).await;
-                pr_discussion::send_message(
+        }
+        Event::PullRequestRenamed(pr) => pr_discussion::rename(ctx, &pr).await?,
+        Event::PullRequestPushed(pr, before, after) => {
+            pr_discussion::commits_pushed(ctx, &pr, &before, &after).await?;
+            pr_discussion::cancel_auto_merge(ctx, pr.number, "new commits were pushed").await?;
+        }
+        Event::PullRequestLabeled(pr) => pr_discussion::labels_changed(ctx, &pr).await?,
+        Event::PullRequestReviewersChanged(pr, requested) => {
+            pr_discussion::update_reviewers(ctx, &pr).await?;
+            let pings = accounts::to_notify(requested.as_deref(), author(&pr));
+            if !pings.is_empty() {
+                pr_discussion::send_relayed(
                     ctx,
                     pr.number,
-                    CreateMessage::new()
-                        .content(format!("Pull request #{} was closed!", pr.number)),
+                    &format!("Review requested on pull request #{}", pr.number),
+                    None,
+                    &pings,
                 )
-                .await;
+                .await?;
             }
-            Event::PullRequestComment(pr, comment, user) => {
-                let comment = comment
-                    .lines()
-                    .map(|l| format!("> {l}"))
-                    .collect::<Vec<String>>()
-                    .join("\n");
-                pr_discussion::send_message(
-                    ctx,
-                    pr,
-                    CreateMessage::new().content(format!("{comment}\n~ {user}")),
-                )
-                .await;
+        }
+        Event::ChecksUpdated(sha, prs) => {
+            pr_discussion::checks_updated(ctx, &sha, prs).await?;
+            for id in AUTO_MERGES.with_sha(&ENV_VARS.repo_slug(), &sha) {
+                pr_discussion::try_auto_merge(ctx, id).await?;
             }
         }
+        Event::PullRequestReviewThreadResolved(pr, root, resolved, user) => {
+            pr_discussion::review_thread_resolved(ctx, pr, root, resolved, &user).await?
+        }
+        Event::PullRequestReviewComment(pr, comment, author) => {
+            pr_discussion::review_comment(ctx, pr, &comment, author.as_deref()).await?
+        }
+        Event::PullRequestComment(pr, comment, user, author) => {
+            let comment = markdown::quote(&markdown::github_to_discord(&comment));
+            let pings = accounts::to_notify(author.as_deref(), Some(&user));
+            let user = accounts::mention(&user);
+            let content = format!("{comment}\n~ {user}");
+            pr_discussion::send_relayed(ctx, pr, &content, None, &pings).await?;
+        }
     }
+
+    Ok(())
 }
 
 #[async_trait]
@@ -114,20 +227,20 @@ impl EventHandler for MainLoop {
         if !self.main_loop_running.load(Ordering::Relaxed) {
             let ctx = Arc::new(ctx);
 
-            let (tx, mut rx) = channel::<Event>(16);
-            TX.set(Mutex::new(tx)).unwrap();
-
-            tokio::spawn(async move {
-                loop {
-                    run_main_loop(&ctx, &mut rx).await;
-                    tokio::time::sleep(Duration::from_millis(100)).await
-                }
-            });
+            tokio::spawn(reconcile::run_periodic(ctx.clone()));
+            // Queued events shouldn't wait for a backfill over every open PR
+            let backfill_ctx = ctx.clone();
+            tokio::spawn(async move { reconcile::backfill(&backfill_ctx).await });
+            tokio::spawn(async move { run_main_loop(&ctx).await });
 
             self.main_loop_running.swap(true, Ordering::Relaxed);
         }
     }
 
+    async fn message(&self, ctx: Context, new_message: Message) {
+        relay::handle_message(&ctx, &new_message).await;
+    }
+
     async fn guild_member_addition(&self, ctx: Context, new_member: Member) {
         if let Err(err) = new_member.add_role(ctx, ENV_VARS.member_role).await {
             error!("Failed assigning member role to new member: {err}");
@@ -154,6 +267,9 @@ async fn setup_bot() {
                 bot::commands::file_search::paths::file_search(),
                 bot::commands::file_search::text::text_search(),
                 bot::commands::merge::merge(),
+                bot::commands::dead_letters::dead_letters(),
+                bot::commands::comment::comment(),
+                bot::commands::link_github::link_github(),
             ],
             ..Default::default()
         })
@@ -185,6 +301,8 @@ async fn main() {
         warn!("Failed loading .env: {err}");
     };
 
+    // Opens (and replays) the event queue before the webhook can push to it
+    info!("{} queued event(s) pending from last run", QUEUE.len());
     tokio::spawn(async move { setup_webhook().await });
 
     setup_bot().await
@@ -0,0 +1,583 @@
+use std::sync::LazyLock;
+
+use regex::{Captures, Regex};
+
+/// Discord's limit for message content.
+pub const MAX_MESSAGE_LEN: usize = 2000;
+
+static IMAGE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"!\[([^\]]*)\]\(([^)\s]+)[^)]*\)").unwrap());
+static HTML_IMAGE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)<img\b[^>]*?\bsrc="([^"]+)"[^>]*>"#).unwrap());
+static DETAILS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)</?details\b[^>]*>").unwrap());
+static SUMMARY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)<summary\b[^>]*>(.*?)</summary>").unwrap());
+static LINE_BREAK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)<br\s*/?>").unwrap());
+
+/// Converts GitHub flavored markdown into something Discord renders sensibly.
+pub fn github_to_discord(text: &str) -> String {
+    let text = strip_html_comments(text);
+
+    let mut out = Vec::new();
+    let mut in_code = false;
+    let mut in_suggestion = false;
+    let mut table = Vec::new();
+    let lines = text.lines().collect::<Vec<_>>();
+    for (i, line) in lines.iter().copied().enumerate() {
+        let trimmed = line.trim_start();
+        // Rows don't need outer pipes, but then only the delimiter row below
+        // the header tells a table apart from text that contains a pipe
+        let starts_table = trimmed.contains('|')
+            && lines
+                .get(i + 1)
+                .is_some_and(|next| is_table_delimiter(next.trim()));
+        let continues_table = !table.is_empty() && trimmed.contains('|');
+        if !in_code && (is_table_row(trimmed) || starts_table || continues_table) {
+            table.push(trimmed.to_string());
+            continue;
+        }
+        flush_table(&mut table, &mut out);
+
+        if let Some(lang) = trimmed.strip_prefix("```") {
+            in_code = !in_code;
+            // Suggestions replace the commented lines, show them as additions
+            if in_code && lang.trim() == "suggestion" {
+                in_suggestion = true;
+                out.push("**Suggested change:**".to_string());
+                out.push(line.replace("suggestion", "diff"));
+            } else {
+                in_suggestion = false;
+                out.push(line.to_string());
+            }
+            continue;
+        }
+        if in_code {
+            if in_suggestion {
+                out.push(format!("+ {line}"));
+            } else {
+                out.push(line.to_string());
+            }
+            continue;
+        }
+
+        out.push(convert_line(line));
+    }
+    flush_table(&mut table, &mut out);
+
+    // Collapse the blank lines left behind by removed comments
+    let mut result = String::new();
+    let mut blank = 0;
+    for line in out.iter().flat_map(|l| l.split('\n')) {
+        if line.trim().is_empty() {
+            blank += 1;
+            if blank > 1 {
+                continue;
+            }
+        } else {
+            blank = 0;
+        }
+        result.push_str(line);
+        result.push('\n');
+    }
+
+    result.trim().to_string()
+}
+
+fn convert_line(line: &str) -> String {
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
+
+    // Discord only knows three heading levels
+    let hashes = trimmed.chars().take_while(|c| *c == '#').count();
+    if hashes > 3 && trimmed[hashes..].starts_with(' ') {
+        return format!("{indent}**{}**", trimmed[hashes..].trim());
+    }
+
+    for (task, symbol) in [("- [ ] ", "☐"), ("- [x] ", "☑"), ("- [X] ", "☑")] {
+        if let Some(rest) = trimmed.strip_prefix(task) {
+            return format!("{indent}- {symbol} {rest}");
+        }
+    }
+
+    // Collapsed sections can't be collapsed on Discord, keep just their title
+    let line = DETAILS.replace_all(line, "");
+    let line = SUMMARY.replace_all(&line, "**▸ $1**");
+    let line = LINE_BREAK.replace_all(&line, "\n");
+
+    // Images can't be embedded inline, link them instead
+    let line = HTML_IMAGE.replace_all(&line, "[image](<$1>)");
+    IMAGE
+        .replace_all(&line, |caps: &Captures| {
+            let alt = caps[1].trim();
+            let alt = if alt.is_empty() { "image" } else { alt };
+            format!("[{alt}](<{}>)", &caps[2])
+        })
+        .into_owned()
+}
+
+fn is_table_row(line: &str) -> bool {
+    line.starts_with('|') && line[1..].contains('|')
+}
+
+/// The `--- | :---:` row between a table's header and its body.
+fn is_table_delimiter(line: &str) -> bool {
+    let cells = line.trim_matches('|').split('|').collect::<Vec<_>>();
+    line.contains('|')
+        && cells.iter().all(|cell| {
+            let cell = cell.trim().trim_start_matches(':').trim_end_matches(':');
+            !cell.is_empty() && cell.chars().all(|c| c == '-')
+        })
+}
+
+/// Discord can't render tables, but they stay readable in a code block.
+fn flush_table(table: &mut Vec<String>, out: &mut Vec<String>) {
+    match table.len() {
+        0 => {}
+        1 => out.append(table),
+        _ => {
+            out.push("```".to_string());
+            out.append(table);
+            out.push("```".to_string());
+        }
+    }
+}
+
+/// Removes `<!-- -->` comments, except inside code blocks.
+fn strip_html_comments(text: &str) -> String {
+    let mut result = Vec::new();
+    let mut in_code = false;
+    let mut in_comment = false;
+    for line in text.lines() {
+        if !in_comment && line.trim_start().starts_with("```") {
+            in_code = !in_code;
+        }
+        if in_code {
+            result.push(line.to_string());
+            continue;
+        }
+
+        let mut kept = String::new();
+        let mut rest = line;
+        loop {
+            if in_comment {
+                let Some(end) = rest.find("-->") else {
+                    break;
+                };
+                rest = &rest[end + 3..];
+                in_comment = false;
+            } else {
+                let Some(start) = rest.find("<!--") else {
+                    kept.push_str(rest);
+                    break;
+                };
+                kept.push_str(&rest[..start]);
+                rest = &rest[start + 4..];
+                in_comment = true;
+            }
+        }
+        result.push(kept);
+    }
+    result.join("\n")
+}
+
+/// Where the fence starts if the line opens or closes a code block, also
+/// inside quotes.
+fn fence_start(line: &str) -> Option<usize> {
+    let stripped = line.trim_start_matches([' ', '>']);
+    stripped
+        .starts_with("```")
+        .then_some(line.len() - stripped.len())
+}
+
+/// The line that opened a code block still open at the end of `text`.
+fn open_fence(text: &str) -> Option<&str> {
+    text.lines()
+        .filter(|l| fence_start(l).is_some())
+        .fold(None, |open, line| match open {
+            Some(_) => None,
+            None => Some(line),
+        })
+}
+
+/// Closes the code block opened by `open`, keeping its quote prefix.
+fn closing_fence(open: &str) -> String {
+    format!("\n{}```", &open[..fence_start(open).unwrap_or(0)])
+}
+
+/// The `> ` markers a quoted line starts with.
+fn quote_prefix(line: &str) -> &str {
+    if !line.starts_with('>') {
+        return "";
+    }
+    &line[..line.len() - line.trim_start_matches(['>', ' ']).len()]
+}
+
+/// Splits `text` into chunks of at most `max` characters at line boundaries.
+/// Lines too long for a message of their own are broken at whitespace, keeping
+/// their quote markers. A code block that gets cut is closed at the end of one
+/// chunk and reopened at the start of the next.
+pub fn split(text: &str, max: usize) -> Vec<String> {
+    if text.trim().is_empty() {
+        return Vec::new();
+    }
+    if text.chars().count() <= max {
+        return vec![text.to_string()];
+    }
+
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+    // Whether the chunk holds nothing but a reopened code block
+    let mut fresh = true;
+    let mut open: Option<String> = None;
+    for line in text.lines() {
+        let toggles = fence_start(line).is_some();
+        // What has to be closed if the chunk ends within or right after this line
+        let open_after = match (&open, toggles) {
+            (Some(_), true) => None,
+            (None, true) => Some(line),
+            (open, false) => open.as_deref(),
+        };
+        let closing_len = |open: Option<&str>| {
+            open.map(|o| closing_fence(o).chars().count())
+                .unwrap_or_default()
+        };
+        // A line that's only partially in a chunk may have to close either
+        let line_closing_len = closing_len(open_after);
+        let piece_closing_len = line_closing_len.max(closing_len(open.as_deref()));
+        let reopened_len = open.as_ref().map(|o| o.chars().count()).unwrap_or_default();
+
+        let prefix = quote_prefix(line);
+        let mut rest = line.to_string();
+        loop {
+            let separator = usize::from(current_len > 0);
+            let rest_len = rest.chars().count();
+            let fits_fresh =
+                reopened_len + usize::from(reopened_len > 0) + rest_len + line_closing_len <= max;
+
+            if current_len + separator + rest_len + line_closing_len <= max {
+                push_line(&mut current, &mut current_len, &rest);
+                fresh = false;
+                break;
+            }
+            if !fresh && fits_fresh {
+                flush(&mut chunks, &mut current, &mut current_len, open.as_deref());
+                fresh = true;
+                continue;
+            }
+
+            // Too long for any chunk, fill up this one and carry on in the next
+            let available = max.saturating_sub(current_len + separator + piece_closing_len);
+            let min_piece = prefix.chars().count() + 1;
+            if available < min_piece && !fresh {
+                flush(&mut chunks, &mut current, &mut current_len, open.as_deref());
+                fresh = true;
+                continue;
+            }
+            let (piece, remainder) = if available >= min_piece {
+                cut_at_whitespace(&rest, available, prefix.len())
+            } else {
+                cut_at_whitespace(&rest, available.max(1), 0)
+            };
+            push_line(&mut current, &mut current_len, &piece);
+            flush(&mut chunks, &mut current, &mut current_len, open.as_deref());
+            fresh = true;
+            rest = format!("{prefix}{remainder}");
+        }
+
+        if toggles {
+            open = match open {
+                Some(_) => None,
+                None => Some(line.to_string()),
+            };
+        }
+    }
+    if !current.trim().is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+fn push_line(current: &mut String, current_len: &mut usize, line: &str) {
+    if *current_len > 0 {
+        current.push('\n');
+        *current_len += 1;
+    }
+    current.push_str(line);
+    *current_len += line.chars().count();
+}
+
+/// Ends the chunk, closing the code block `open` and reopening it in the next.
+fn flush(
+    chunks: &mut Vec<String>,
+    current: &mut String,
+    current_len: &mut usize,
+    open: Option<&str>,
+) {
+    if let Some(open) = open {
+        current.push_str(&closing_fence(open));
+    }
+    chunks.push(std::mem::take(current));
+    *current_len = 0;
+    if let Some(open) = open {
+        push_line(current, current_len, open);
+    }
+}
+
+/// Cuts `text` after at most `max` characters, at the last whitespace past the
+/// first `skip` bytes if there is any, and drops the whitespace.
+fn cut_at_whitespace(text: &str, max: usize, skip: usize) -> (String, String) {
+    let end = text
+        .char_indices()
+        .nth(max)
+        .map(|(i, _)| i)
+        .unwrap_or(text.len());
+    let head = &text[..end];
+    match head[skip.min(end)..].rfind(char::is_whitespace) {
+        Some(pos) if skip + pos > 0 => {
+            let pos = skip + pos;
+            (
+                head[..pos].to_string(),
+                text[pos..].trim_start().to_string(),
+            )
+        }
+        _ => (head.to_string(), text[end..].to_string()),
+    }
+}
+
+/// Prefixes every line with `> `.
+pub fn quote(text: &str) -> String {
+    text.lines()
+        .map(|l| format!("> {l}"))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Shortens `text` to at most `max` characters, preferring line boundaries
+/// and closing any code block that was cut off.
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+
+    // The fence to close depends on where the cut lands, so shrink until it fits
+    let mut budget = max.saturating_sub(1);
+    loop {
+        let mut cut = text.chars().take(budget).collect::<String>();
+        if let Some(pos) = cut.rfind('\n')
+            && pos > budget / 2
+        {
+            cut.truncate(pos);
+        }
+        let closing = open_fence(&cut).map(closing_fence).unwrap_or_default();
+
+        let len = cut.chars().count() + 1 + closing.chars().count();
+        if len <= max || budget == 0 {
+            return format!("{cut}…{closing}");
+        }
+        budget = budget.saturating_sub(len - max);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_github_markdown() {
+        let cases = [
+            // Fences and headings
+            ("```rust\nlet a = 1;\n```", "```rust\nlet a = 1;\n```"),
+            ("#### Deep heading", "**Deep heading**"),
+            ("### Shallow heading", "### Shallow heading"),
+            ("- [ ] todo\n- [x] done", "- ☐ todo\n- ☑ done"),
+            // Images
+            (
+                "![logo](https://x.io/a.png)",
+                "[logo](<https://x.io/a.png>)",
+            ),
+            (
+                "![](https://x.io/a.png \"title\")",
+                "[image](<https://x.io/a.png>)",
+            ),
+            (
+                r#"<img width="200" src="https://x.io/a.png" />"#,
+                "[image](<https://x.io/a.png>)",
+            ),
+            // Tables, with and without outer pipes
+            (
+                "| a | b |\n|---|---|\n| 1 | 2 |",
+                "```\n| a | b |\n|---|---|\n| 1 | 2 |\n```",
+            ),
+            (
+                "a | b\n--- | :---:\n1 | 2\nafter",
+                "```\na | b\n--- | :---:\n1 | 2\n```\nafter",
+            ),
+            ("either this | or that", "either this | or that"),
+            ("a | b\n---", "a | b\n---"),
+            // Collapsed sections and line breaks
+            (
+                "<details>\n<summary>Logs</summary>\n\nfailed\n</details>",
+                "**▸ Logs**\n\nfailed",
+            ),
+            ("one<br>two", "one\ntwo"),
+            // Suggestions
+            (
+                "```suggestion\nlet a = 2;\n```",
+                "**Suggested change:**\n```diff\n+ let a = 2;\n```",
+            ),
+            // Comments, also spanning lines
+            ("before <!-- hidden --> after", "before  after"),
+            ("a\n<!--\nhidden\n-->\nb", "a\n\nb"),
+        ];
+        for (github, discord) in cases {
+            assert_eq!(github_to_discord(github), discord, "converting {github:?}");
+        }
+    }
+
+    #[test]
+    fn leaves_code_blocks_alone() {
+        let cases = [
+            "```\n<!-- kept -->\n```",
+            "```\n@someone\n```",
+            "```html\n<details><summary>x</summary></details>\n<img src=\"a.png\">\n```",
+            "```\n![alt](a.png)\n#### not a heading\n```",
+            "```\n| a | b |\n|---|---|\n```",
+        ];
+        for code in cases {
+            assert_eq!(github_to_discord(code), code);
+        }
+        assert_eq!(github_to_discord("cc @someone"), "cc @someone");
+    }
+
+    fn assert_chunks_valid(chunks: &[String], max: usize) {
+        for chunk in chunks {
+            assert!(
+                chunk.chars().count() <= max,
+                "chunk of {} > {max} chars:\n{chunk}",
+                chunk.chars().count()
+            );
+            assert!(open_fence(chunk).is_none(), "unclosed fence in:\n{chunk}");
+        }
+    }
+
+    #[test]
+    fn split_keeps_short_text_whole() {
+        assert_eq!(split("one\ntwo", 100), vec!["one\ntwo"]);
+    }
+
+    #[test]
+    fn split_keeps_long_paragraphs_that_fit() {
+        let para = format!(
+            "{} see the [docs](https://example.com/some/long/path) for more.",
+            "Lorem ipsum dolor sit amet. ".repeat(17)
+        );
+        assert!(para.chars().count() > 500);
+        assert_eq!(split(&para, 2000), vec![para.clone()]);
+
+        // Moved to the next chunk whole rather than broken up
+        let text = format!("{}\n{para}", "intro\n".repeat(300));
+        let chunks = split(&text, 2000);
+        assert_chunks_valid(&chunks, 2000);
+        assert_eq!(chunks.last().unwrap(), &para);
+    }
+
+    #[test]
+    fn split_breaks_over_long_lines_at_whitespace() {
+        let line = format!("> {}", "word ".repeat(100).trim_end());
+        let chunks = split(&format!("intro\n{line}"), 120);
+        assert_chunks_valid(&chunks, 120);
+        assert!(chunks[0].starts_with("intro\n> word"));
+        for chunk in &chunks {
+            for line in chunk.lines().skip_while(|l| *l == "intro") {
+                assert!(line.starts_with("> "), "lost quote in {line:?}");
+                assert!(
+                    line[2..].split(' ').all(|w| w == "word"),
+                    "cut word in {line:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn split_breaks_at_lines() {
+        let text = (0..30).map(|i| format!("line {i}")).collect::<Vec<_>>();
+        let chunks = split(&text.join("\n"), 40);
+        assert_chunks_valid(&chunks, 40);
+        assert_eq!(chunks.join("\n"), text.join("\n"));
+    }
+
+    #[test]
+    fn split_reopens_cut_code_blocks() {
+        let code = (0..40)
+            .map(|i| format!("let x{i} = {i};"))
+            .collect::<Vec<_>>();
+        let text = format!("```rust\n{}\n```", code.join("\n"));
+        let chunks = split(&text, 100);
+        assert!(chunks.len() > 1);
+        assert_chunks_valid(&chunks, 100);
+        assert!(chunks[1..].iter().all(|c| c.starts_with("```rust\n")));
+    }
+
+    #[test]
+    fn split_budgets_quoted_fence_opened_at_chunk_end() {
+        // The opening line lands right at the end of the first chunk
+        for filler in 80..100 {
+            let text = format!(
+                "{}\n> ```rust\n> let a = 1;\n> let b = 2;\n> ```",
+                "a".repeat(filler)
+            );
+            let chunks = split(&text, 100);
+            assert_chunks_valid(&chunks, 100);
+            assert!(chunks.iter().all(|c| !c.ends_with("```rust")));
+        }
+    }
+
+    #[test]
+    fn split_never_exceeds_max() {
+        let text = format!(
+            "intro\n> ```diff\n{}\n> ```\n```\n{}\n```\n{}",
+            (0..50)
+                .map(|i| format!("> + added line {i}"))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            "x".repeat(700),
+            "outro ".repeat(100)
+        );
+        for max in [20, 37, 64, 100, 150, 333, 2000] {
+            assert_chunks_valid(&split(&text, max), max);
+        }
+    }
+
+    #[test]
+    fn truncate_keeps_short_text() {
+        assert_eq!(truncate("short", 10), "short");
+    }
+
+    #[test]
+    fn truncate_cuts_plain_text() {
+        let cut = truncate(&"word ".repeat(100), 50);
+        assert!(cut.chars().count() <= 50);
+        assert!(cut.ends_with('…'));
+    }
+
+    #[test]
+    fn truncate_closes_code_blocks() {
+        let text = format!("```\n{}\n```", "code\n".repeat(100));
+        let cut = truncate(&text, 60);
+        assert!(cut.chars().count() <= 60);
+        assert!(cut.ends_with("\n```"));
+        assert!(open_fence(&cut).is_none());
+    }
+
+    #[test]
+    fn truncate_closes_quoted_code_blocks() {
+        let text = format!("> ```rust\n{}> ```", "> let x = 1;\n".repeat(100));
+        for max in [30, 61, 100, 500] {
+            let cut = truncate(&text, max);
+            assert!(cut.chars().count() <= max, "{cut}");
+            assert!(cut.ends_with("\n> ```"), "{cut}");
+            assert!(open_fence(&cut).is_none());
+        }
+    }
+}
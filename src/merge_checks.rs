@@ -0,0 +1,135 @@
+use std::fmt;
+
+use octocrab::{
+    Octocrab,
+    models::pulls::{Merge, MergeableState, PullRequest, ReviewState},
+    params::pulls::MergeMethod,
+};
+
+use crate::{
+    ENV_VARS,
+    approvals::{self, ApprovalStatus},
+    pr_discussion,
+};
+
+/// Something that should stop a PR from being merged right now.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Blocker {
+    Closed,
+    Draft,
+    Conflicts,
+    /// GitHub hasn't finished computing whether the PR can be merged.
+    MergeabilityUnknown,
+    Behind(String),
+    NotApproved(ApprovalStatus),
+    ChangesRequested(Vec<String>),
+    ChecksFailed(Vec<String>),
+    ChecksPending(usize),
+}
+
+impl fmt::Display for Blocker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Closed => write!(f, "The pull request is closed"),
+            Self::Draft => write!(f, "The pull request is a draft"),
+            Self::Conflicts => write!(f, "The pull request has merge conflicts"),
+            Self::MergeabilityUnknown => write!(f, "GitHub is still checking for conflicts"),
+            Self::Behind(base) => write!(f, "The branch is behind `{base}`"),
+            Self::NotApproved(status) => write!(f, "Not approved yet ({status})"),
+            Self::ChangesRequested(users) => {
+                write!(f, "Changes requested by **{}**", users.join("**, **"))
+            }
+            Self::ChecksFailed(names) => write!(f, "Failing checks: {}", names.join(", ")),
+            Self::ChecksPending(count) => write!(f, "{count} check(s) still running"),
+        }
+    }
+}
+
+/// A PR along with everything currently standing in the way of merging it.
+pub struct MergeReadiness {
+    pub pr: PullRequest,
+    pub blockers: Vec<Blocker>,
+}
+
+impl MergeReadiness {
+    pub fn is_ready(&self) -> bool {
+        self.blockers.is_empty()
+    }
+}
+
+/// Fetches the PR and checks its state, reviews and CI for anything blocking a merge.
+pub async fn check(client: &Octocrab, id: u64) -> Result<MergeReadiness, octocrab::Error> {
+    let pr = client
+        .pulls(&ENV_VARS.repo_owner, &ENV_VARS.repo)
+        .get(id)
+        .await?;
+
+    let mut blockers = Vec::new();
+    if pr.closed_at.is_some() {
+        blockers.push(Blocker::Closed);
+    }
+    if pr.draft.unwrap_or_default() {
+        blockers.push(Blocker::Draft);
+    }
+    match (pr.mergeable, &pr.mergeable_state) {
+        (Some(false), _) | (_, Some(MergeableState::Dirty)) => blockers.push(Blocker::Conflicts),
+        (None, _) | (_, Some(MergeableState::Unknown)) => {
+            blockers.push(Blocker::MergeabilityUnknown)
+        }
+        (_, Some(MergeableState::Behind)) => {
+            blockers.push(Blocker::Behind(pr.base.ref_field.clone()))
+        }
+        _ => {}
+    }
+
+    let reviews = approvals::latest_reviews(client, id).await?;
+    let mut requesters = reviews
+        .iter()
+        .filter(|(_, r)| r.state == ReviewState::ChangesRequested)
+        .map(|(login, _)| login.clone())
+        .collect::<Vec<_>>();
+    if !requesters.is_empty() {
+        requesters.sort();
+        blockers.push(Blocker::ChangesRequested(requesters));
+    }
+    let status = approvals::status(client, &pr, &reviews).await?;
+    if !status.is_met() {
+        blockers.push(Blocker::NotApproved(status));
+    }
+
+    let runs = pr_discussion::check_runs(client, &pr.head.sha).await?;
+    let failed = runs
+        .iter()
+        .filter(|run| pr_discussion::check_failed(run))
+        .map(|run| run.name.clone())
+        .collect::<Vec<_>>();
+    if !failed.is_empty() {
+        blockers.push(Blocker::ChecksFailed(failed));
+    }
+    let pending = runs.iter().filter(|run| run.conclusion.is_none()).count();
+    if pending > 0 {
+        blockers.push(Blocker::ChecksPending(pending));
+    }
+
+    Ok(MergeReadiness { pr, blockers })
+}
+
+/// Merges the PR, failing if its head moved past `sha` since it was checked.
+pub async fn merge(
+    client: &Octocrab,
+    id: u64,
+    sha: &str,
+    method: MergeMethod,
+    title: Option<String>,
+    message: String,
+) -> Result<Merge, octocrab::Error> {
+    let pulls = client.pulls(&ENV_VARS.repo_owner, &ENV_VARS.repo);
+    let merge = pulls.merge(id).message(message).method(method).sha(sha);
+
+    // Jank because title() and the like consume self
+    if let Some(title) = title {
+        merge.title(title).send().await
+    } else {
+        merge.send().await
+    }
+}
@@ -1,88 +1,1156 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, LazyLock, Mutex},
+};
 
-use octocrab::models::pulls::PullRequest;
+use octocrab::{
+    Octocrab,
+    commits::PullRequestTarget,
+    models::{
+        checks::CheckRun,
+        commits::{Commit, GithubCommitStatus},
+        pulls::{Comment, PullRequest, ReviewState},
+    },
+    params::repos::Commitish,
+};
 use poise::serenity_prelude::{
-    ChannelId, Context, CreateForumPost, CreateMessage, EditThread, ForumTagId, GuildChannel,
+    ChannelId, Context, CreateAllowedMentions, CreateEmbed, CreateEmbedAuthor, CreateEmbedFooter,
+    CreateForumPost, CreateMessage, EditMessage, EditThread, Error as SerenityError, ForumTagId,
+    GuildChannel, Message, MessageId, MessageReference, MessageReferenceKind, Timestamp, UserId,
+};
+use regex::Regex;
+use tokio::sync::Mutex as AsyncMutex;
+use tracing::{error, info, warn};
+
+use crate::{
+    AUTO_MERGES, ENV_VARS, REVIEW_COMMENTS, STATUS_MESSAGES, THREADS, accounts, approvals,
+    github_client, markdown,
+    merge_checks::{self, Blocker},
+    retry::{self, RetryPolicy},
 };
-use tracing::error;
 
-use crate::ENV_VARS;
+/// How many pages of archived threads the name scan looks through.
+const MAX_ARCHIVE_PAGES: usize = 10;
+
+/// Discord's limit for channel names.
+const MAX_THREAD_NAME_LEN: usize = 100;
+
+/// Discord's limits for embed titles and descriptions.
+const MAX_EMBED_TITLE_LEN: usize = 256;
+const MAX_EMBED_DESCRIPTION_LEN: usize = 4096;
+
+/// Discord's limit for tags applied to a forum post.
+const MAX_APPLIED_TAGS: usize = 5;
+
+/// Commit summaries list at most this many commits, the newest ones.
+const MAX_LISTED_COMMITS: usize = 10;
+const MAX_COMMIT_SUBJECT_LEN: usize = 72;
+
+/// Review comments show the last lines of their diff hunk, which end at the
+/// commented line.
+const MAX_HUNK_LINES: usize = 6;
+const MAX_HUNK_LINE_LEN: usize = 120;
+
+/// Title of the pinned reviewer roster embed, which is how it's found again.
+const ROSTER_TITLE: &str = "Reviewers";
+
+const COLOR_OPEN: u32 = 0x238636;
+const COLOR_DRAFT: u32 = 0x6e7681;
+const COLOR_CHECKS_PENDING: u32 = 0xd29922;
+const COLOR_CHECKS_FAILED: u32 = 0xda3633;
+
+/// Check run conclusions that count as a failed job.
+const FAILED_CONCLUSIONS: [&str; 5] = [
+    "failure",
+    "timed_out",
+    "cancelled",
+    "action_required",
+    "startup_failure",
+];
+
+#[derive(Debug)]
+pub enum DiscussionError {
+    MissingPost(u64),
+    MissingUrl(u64),
+    EmptyMessage(u64),
+    Discord(SerenityError),
+    GitHub(octocrab::Error),
+}
+
+impl fmt::Display for DiscussionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingPost(id) => write!(f, "missing forum post for PR #{id}"),
+            Self::MissingUrl(id) => write!(f, "PR #{id} is missing its HTML URL"),
+            Self::EmptyMessage(id) => write!(f, "tried sending an empty message for PR #{id}"),
+            Self::Discord(err) => write!(f, "{err}"),
+            Self::GitHub(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for DiscussionError {}
+
+impl From<SerenityError> for DiscussionError {
+    fn from(err: SerenityError) -> Self {
+        Self::Discord(err)
+    }
+}
+
+impl From<octocrab::Error> for DiscussionError {
+    fn from(err: octocrab::Error) -> Self {
+        Self::GitHub(err)
+    }
+}
+
+/// `#N - title by author`, with the title shortened to fit Discord's limit.
+/// The `#N` prefix must stay intact for [`find_pr_from_post`].
+fn post_name(pr: &PullRequest) -> String {
+    let prefix = format!("#{} - ", pr.number);
+    let suffix = format!(
+        " by {}",
+        pr.user
+            .as_ref()
+            .map(|u| u.login.as_str())
+            .unwrap_or("Unknown")
+    );
+    let title = pr.title.as_deref().unwrap_or("Unnamed");
+
+    let budget =
+        MAX_THREAD_NAME_LEN.saturating_sub(prefix.chars().count() + suffix.chars().count());
+    let title = if title.chars().count() > budget {
+        let mut short = title
+            .chars()
+            .take(budget.saturating_sub(1))
+            .collect::<String>();
+        short.push('…');
+        short
+    } else {
+        title.to_string()
+    };
+
+    format!("{prefix}{title}{suffix}")
+}
+
+static LINKED_ISSUE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(?:close[sd]?|fix(?:e[sd])?|resolve[sd]?):?\s+#(\d+)\b").unwrap()
+});
+
+/// Issues the PR body closes through GitHub's closing keywords, e.g. `Fixes #12`.
+fn linked_issues(body: &str) -> Vec<u64> {
+    let mut issues = Vec::new();
+    for capture in LINKED_ISSUE.captures_iter(body) {
+        if let Ok(id) = capture[1].parse::<u64>()
+            && !issues.contains(&id)
+        {
+            issues.push(id);
+        }
+    }
+    issues
+}
+
+fn pr_embed(pr: &PullRequest) -> CreateEmbed {
+    let mut embed = CreateEmbed::new()
+        .title(markdown::truncate(
+            pr.title.as_deref().unwrap_or("Unnamed"),
+            MAX_EMBED_TITLE_LEN,
+        ))
+        .color(if pr.draft.unwrap_or_default() {
+            COLOR_DRAFT
+        } else {
+            COLOR_OPEN
+        });
+
+    if let Some(url) = &pr.html_url {
+        embed = embed.url(url.as_str());
+    }
+    if let Some(user) = &pr.user {
+        embed = embed.author(
+            CreateEmbedAuthor::new(&user.login)
+                .icon_url(user.avatar_url.as_str())
+                .url(user.html_url.as_str()),
+        );
+    }
+    if let Some(created) = pr
+        .created_at
+        .and_then(|t| Timestamp::from_unix_timestamp(t.timestamp()).ok())
+    {
+        embed = embed.timestamp(created);
+    }
+
+    let body = pr.body.as_deref().unwrap_or_default();
+    if !body.trim().is_empty() {
+        embed = embed.description(markdown::truncate(
+            &markdown::github_to_discord(body),
+            MAX_EMBED_DESCRIPTION_LEN,
+        ));
+    }
 
-pub async fn pr_created(ctx: &Arc<Context>, pr: PullRequest) {
-    let name = format!(
-        "#{} - {} by {}",
-        pr.number,
-        pr.title.unwrap_or("Unnamed".to_string()),
-        pr.user.map(|u| u.login).unwrap_or("Unknown".to_string())
+    let head = pr.head.label.as_deref().unwrap_or(&pr.head.ref_field);
+    embed = embed.field(
+        "Branches",
+        format!("`{head}` → `{}`", pr.base.ref_field),
+        false,
     );
 
-    let url = pr
-        .html_url
-        .expect("PR missing HTML URL?")
-        .as_str()
-        .to_string();
+    if let (Some(additions), Some(deletions), Some(files)) =
+        (pr.additions, pr.deletions, pr.changed_files)
+    {
+        embed = embed.field(
+            "Changes",
+            format!("+{additions} −{deletions} in {files} file(s)"),
+            true,
+        );
+    }
+
+    let labels = pr
+        .labels
+        .iter()
+        .flatten()
+        .map(|l| format!("`{}`", l.name))
+        .collect::<Vec<_>>();
+    if !labels.is_empty() {
+        embed = embed.field("Labels", labels.join(" "), true);
+    }
+
+    let issues = linked_issues(body)
+        .into_iter()
+        .map(|id| {
+            format!(
+                "[#{id}](https://github.com/{}/issues/{id})",
+                ENV_VARS.repo_slug()
+            )
+        })
+        .collect::<Vec<_>>();
+    if !issues.is_empty() {
+        embed = embed.field("Linked issues", issues.join(", "), false);
+    }
+
+    embed
+}
+
+/// Held per PR while checking for and creating its forum post, so the startup
+/// backfill and a queued opened event can't both create one.
+static POST_CREATION: LazyLock<Mutex<HashMap<u64, Arc<AsyncMutex<()>>>>> =
+    LazyLock::new(Default::default);
+
+pub async fn pr_created(ctx: &Arc<Context>, pr: PullRequest) -> Result<(), DiscussionError> {
+    let lock = POST_CREATION
+        .lock()
+        .unwrap()
+        .entry(pr.number)
+        .or_default()
+        .clone();
+    let _guard = lock.lock().await;
+
+    if mapped_thread(ctx, pr.number).await?.is_some() {
+        info!("Forum post for PR #{} already exists", pr.number);
+        return Ok(());
+    }
+
+    let name = post_name(&pr);
+
+    if pr.html_url.is_none() {
+        return Err(DiscussionError::MissingUrl(pr.number));
+    }
     let tag = if pr.draft.unwrap_or_default() {
         ENV_VARS.tag_draft
     } else {
         ENV_VARS.tag_review_needed
     };
-    let post = CreateForumPost::new(name, CreateMessage::new().content(url)).add_applied_tag(tag);
+    let mut post =
+        CreateForumPost::new(name, CreateMessage::new().embed(pr_embed(&pr))).add_applied_tag(tag);
+    for label in label_tags(&pr).into_iter().take(MAX_APPLIED_TAGS - 1) {
+        post = post.add_applied_tag(label);
+    }
+
+    let thread = RetryPolicy::DISCORD
+        .run_create("Creating PR forum post", || {
+            ENV_VARS.pr_channel.create_forum_post(ctx, post.clone())
+        })
+        .await?;
+    THREADS.insert(&ENV_VARS.repo_slug(), pr.number, thread.id);
+
+    // The post exists now, so failing here would only lose the event on replay
+    let logins = pr.user.iter().map(|u| u.login.as_str()).chain(
+        pr.requested_reviewers
+            .iter()
+            .flatten()
+            .map(|u| u.login.as_str()),
+    );
+    for user in accounts::to_notify(logins, None) {
+        let result = RetryPolicy::DISCORD
+            .run(
+                &format!("Adding member to thread for PR #{}", pr.number),
+                || thread.id.add_thread_member(ctx, user),
+            )
+            .await;
+        if let Err(err) = result {
+            error!(
+                "Failed adding {user} to thread for PR #{}: {err}",
+                pr.number
+            );
+        }
+    }
+    Ok(())
+}
 
-    if let Err(err) = ENV_VARS.pr_channel.create_forum_post(ctx, post).await {
-        error!("Failed creating PR forum post: {err}");
+/// Renames the forum post of a PR after its title was edited.
+pub async fn rename(ctx: &Arc<Context>, pr: &PullRequest) -> Result<(), DiscussionError> {
+    let id = pr.number;
+    let thread = find_pr_thread(ctx, id).await?;
+    let name = post_name(pr);
+    if thread.name == name {
+        return Ok(());
+    }
+
+    info!("Renaming forum post for PR #{id} to {name:?}");
+    RetryPolicy::DISCORD
+        .run(&format!("Renaming thread for PR #{id}"), || {
+            thread
+                .id
+                .edit_thread(ctx, EditThread::new().archived(false).name(name.clone()))
+        })
+        .await?;
+    Ok(())
+}
+
+fn short_sha(sha: &str) -> &str {
+    sha.get(..7).unwrap_or(sha)
+}
+
+fn commit_line(commit: &Commit) -> String {
+    let subject = commit.commit.message.lines().next().unwrap_or_default();
+    let author = commit
+        .author
+        .as_ref()
+        .map(|a| a.login.clone())
+        .or_else(|| commit.commit.author.as_ref().and_then(|a| a.name.clone()))
+        .unwrap_or("unknown".to_string());
+
+    format!(
+        "[`{}`](<{}>) {} - {author}",
+        short_sha(&commit.sha),
+        commit.html_url,
+        markdown::truncate(subject, MAX_COMMIT_SUBJECT_LEN)
+    )
+}
+
+/// Posts the commits pushed to a PR, or a single note if the branch was force-pushed.
+pub async fn commits_pushed(
+    ctx: &Arc<Context>,
+    pr: &PullRequest,
+    before: &str,
+    after: &str,
+) -> Result<(), DiscussionError> {
+    let comparison = github_client()
+        .commits(&ENV_VARS.repo_owner, &ENV_VARS.repo)
+        .compare(before, after)
+        .send()
+        .await;
+
+    let force_pushed = || {
+        format!(
+            "Branch `{}` was force-pushed from [`{}`](<https://github.com/{slug}/commit/{before}>) to [`{}`](<https://github.com/{slug}/commit/{after}>)",
+            pr.head.ref_field,
+            short_sha(before),
+            short_sha(after),
+            slug = ENV_VARS.repo_slug(),
+        )
+    };
+    let content = match comparison {
+        Ok(comparison) if matches!(comparison.status, GithubCommitStatus::Ahead) => {
+            let mut lines = vec![format!(
+                "**{}** new commit(s) pushed to `{}`:",
+                comparison.total_commits, pr.head.ref_field
+            )];
+            let skipped = comparison.commits.len().saturating_sub(MAX_LISTED_COMMITS);
+            lines.extend(comparison.commits.iter().skip(skipped).map(commit_line));
+            if skipped > 0 {
+                lines.push(format!("*...and {skipped} older commit(s)*"));
+            }
+            lines.join("\n")
+        }
+        Ok(comparison) if matches!(comparison.status, GithubCommitStatus::Identical) => {
+            return Ok(());
+        }
+        // The old head may already be gone after a force-push, so GitHub can't compare it
+        Ok(_) => force_pushed(),
+        Err(octocrab::Error::GitHub { source, .. })
+            if matches!(source.status_code.as_u16(), 404 | 422) =>
+        {
+            force_pushed()
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    // Commit messages can contain anything, including mentions
+    send_relayed(ctx, pr.number, &content, None, &[]).await?;
+    Ok(())
+}
+
+/// Notes a review thread being resolved or unresolved as a reply to its first
+/// comment. Threads relayed before replies were tracked are left alone.
+pub async fn review_thread_resolved(
+    ctx: &Arc<Context>,
+    id: u64,
+    root: u64,
+    resolved: bool,
+    user: &str,
+) -> Result<(), DiscussionError> {
+    let Some(message) = REVIEW_COMMENTS.get(&ENV_VARS.repo_slug(), root) else {
+        return Ok(());
     };
+
+    let channel = find_pr_thread(ctx, id).await?.id;
+    let reference = MessageReference::new(MessageReferenceKind::Default, channel)
+        .message_id(message)
+        .fail_if_not_exists(false);
+    let content = if resolved {
+        format!("✅ Thread resolved by {}", accounts::mention(user))
+    } else {
+        format!("Thread unresolved by {}", accounts::mention(user))
+    };
+    send_relayed(ctx, id, &content, Some(reference), &[]).await?;
+    Ok(())
 }
 
-pub async fn apply_tag(ctx: &Arc<Context>, id: u64, tag: ForumTagId) {
-    let Some(channel) = find_pr_post(ctx, id).await else {
-        error!("Missing forum post for PR #{id}");
-        return;
+/// `path` and the commented lines, linked to them at the commit the comment was made on.
+fn comment_location(comment: &Comment) -> String {
+    let (lines, anchor) = match (comment.start_line, comment.line) {
+        (Some(start), Some(end)) if start != end => {
+            (format!(" lines {start}-{end}"), format!("#L{start}-L{end}"))
+        }
+        (_, Some(line)) => (format!(" line {line}"), format!("#L{line}")),
+        // Comments on a whole file or on an outdated diff
+        _ => (String::new(), String::new()),
     };
 
-    let edit = EditThread::new().applied_tags(vec![tag]);
-    if let Err(err) = channel.edit_thread(ctx, edit).await {
-        error!("Failed editing thread for PR #{id}: {err}");
+    // Lines on the left side are from the base, link to the diff instead
+    let url = if comment.side.as_deref() == Some("RIGHT") && !anchor.is_empty() {
+        format!(
+            "https://github.com/{}/blob/{}/{}{anchor}",
+            ENV_VARS.repo_slug(),
+            comment.commit_id,
+            comment.path
+        )
+    } else {
+        comment.html_url.clone()
+    };
+
+    format!("[`{}`{lines}](<{url}>)", comment.path)
+}
+
+fn hunk_excerpt(hunk: &str) -> String {
+    // The first line is the `@@ -a,b +c,d @@` header
+    let lines = hunk.lines().skip(1).collect::<Vec<_>>();
+    let skipped = lines.len().saturating_sub(MAX_HUNK_LINES);
+    let excerpt = lines[skipped..]
+        .iter()
+        .map(|l| {
+            l.chars()
+                .take(MAX_HUNK_LINE_LEN)
+                .collect::<String>()
+                .replace("```", "`\u{200b}``")
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!("```diff\n{excerpt}\n```")
+}
+
+/// Relays a review comment with its file and diff context. Replies in a review
+/// thread are sent as Discord replies to the thread's first comment instead.
+/// The PR's `author` is pinged unless they wrote the comment.
+pub async fn review_comment(
+    ctx: &Arc<Context>,
+    id: u64,
+    comment: &Comment,
+    author: Option<&str>,
+) -> Result<(), DiscussionError> {
+    let repo = ENV_VARS.repo_slug();
+    let user = accounts::mention(
+        comment
+            .user
+            .as_ref()
+            .map(|u| u.login.as_str())
+            .unwrap_or("unknown"),
+    );
+    let body = markdown::quote(&markdown::github_to_discord(&comment.body));
+    let pings = accounts::to_notify(author, comment.user.as_ref().map(|u| u.login.as_str()));
+
+    let root = comment.in_reply_to_id.unwrap_or(comment.id).into_inner();
+    let reply_to = comment
+        .in_reply_to_id
+        .and_then(|_| REVIEW_COMMENTS.get(&repo, root));
+
+    let sent = match reply_to {
+        Some(message) => {
+            let channel = find_pr_thread(ctx, id).await?.id;
+            // Still posted if the first comment's message was deleted
+            let reference = MessageReference::new(MessageReferenceKind::Default, channel)
+                .message_id(message)
+                .fail_if_not_exists(false);
+            send_relayed(
+                ctx,
+                id,
+                &format!("{body}\n~ {user}"),
+                Some(reference),
+                &pings,
+            )
+            .await?
+        }
+        None => {
+            let content = format!(
+                "{user} commented on {}:\n{}\n{body}",
+                comment_location(comment),
+                hunk_excerpt(&comment.diff_hunk)
+            );
+            send_relayed(ctx, id, &content, None, &pings).await?
+        }
+    };
+
+    if reply_to.is_none() {
+        REVIEW_COMMENTS.insert(&repo, root, sent.id);
+    }
+    Ok(())
+}
+
+pub fn check_failed(run: &CheckRun) -> bool {
+    run.conclusion
+        .as_deref()
+        .is_some_and(|c| FAILED_CONCLUSIONS.contains(&c))
+}
+
+fn check_line(run: &CheckRun) -> String {
+    match run.conclusion.as_deref() {
+        None => format!("⏳ {}", run.name),
+        Some("success") => format!("✅ {}", run.name),
+        Some(conclusion) if check_failed(run) => {
+            let name = match &run.html_url {
+                Some(url) => format!("[{}](<{url}>)", run.name),
+                None => run.name.clone(),
+            };
+            format!("❌ {name} - {}", conclusion.replace('_', " "))
+        }
+        Some(conclusion) => format!("➖ {} - {}", run.name, conclusion.replace('_', " ")),
     }
 }
 
-pub async fn send_message(ctx: &Arc<Context>, id: u64, message: CreateMessage) {
-    let Some(channel) = find_pr_post(ctx, id).await else {
-        error!("Missing forum post for PR #{id}");
-        return;
+/// One line per job, failures first so their log links are visible right away.
+fn checks_embed(pr: &PullRequest, mut runs: Vec<CheckRun>) -> CreateEmbed {
+    runs.sort_by_key(|run| {
+        (
+            !check_failed(run),
+            run.conclusion.is_some(),
+            run.name.clone(),
+        )
+    });
+
+    let failed = runs.iter().filter(|run| check_failed(run)).count();
+    let pending = runs.iter().filter(|run| run.conclusion.is_none()).count();
+    let passed = runs.len() - failed - pending;
+
+    let lines = runs.iter().map(check_line).collect::<Vec<_>>();
+    let mut embed = CreateEmbed::new()
+        .title(format!("Checks for {}", short_sha(&pr.head.sha)))
+        .description(markdown::truncate(
+            &lines.join("\n"),
+            MAX_EMBED_DESCRIPTION_LEN,
+        ))
+        .footer(CreateEmbedFooter::new(format!(
+            "{passed} passed, {failed} failed, {pending} pending"
+        )))
+        .color(if failed > 0 {
+            COLOR_CHECKS_FAILED
+        } else if pending > 0 {
+            COLOR_CHECKS_PENDING
+        } else {
+            COLOR_OPEN
+        });
+
+    if let Some(url) = &pr.html_url {
+        embed = embed.url(format!("{url}/checks"));
+    }
+    embed
+}
+
+/// Open PRs whose head is this commit, for check events GitHub didn't associate
+/// with any, e.g. ones from forks.
+async fn prs_for_commit(sha: &str) -> Result<Vec<u64>, DiscussionError> {
+    let client = github_client();
+    let page = client
+        .commits(&ENV_VARS.repo_owner, &ENV_VARS.repo)
+        .associated_pull_requests(PullRequestTarget::Sha(sha.to_string()))
+        .per_page(100)
+        .send()
+        .await?;
+
+    Ok(page
+        .items
+        .into_iter()
+        .filter(|pr| pr.head.sha == sha && pr.closed_at.is_none())
+        .map(|pr| pr.number)
+        .collect())
+}
+
+/// The check runs reported for a commit.
+pub async fn check_runs(client: &Octocrab, sha: &str) -> Result<Vec<CheckRun>, octocrab::Error> {
+    Ok(client
+        .checks(&ENV_VARS.repo_owner, &ENV_VARS.repo)
+        .list_check_runs_for_git_ref(Commitish(sha.to_string()))
+        .per_page(100)
+        .send()
+        .await?
+        .check_runs)
+}
+
+/// Updates the CI status message in the forum posts of the PRs a commit belongs to.
+pub async fn checks_updated(
+    ctx: &Arc<Context>,
+    sha: &str,
+    prs: Vec<u64>,
+) -> Result<(), DiscussionError> {
+    let prs = if prs.is_empty() {
+        prs_for_commit(sha).await?
+    } else {
+        prs
+    };
+
+    let client = github_client();
+    for id in prs {
+        // Checks of an older head are outdated, and closed PRs keep their last status
+        let pr = client
+            .pulls(&ENV_VARS.repo_owner, &ENV_VARS.repo)
+            .get(id)
+            .await?;
+        if pr.head.sha != sha || pr.closed_at.is_some() {
+            continue;
+        }
+        if find_post(ctx, id).await?.is_none() {
+            continue;
+        }
+
+        let runs = check_runs(&client, sha).await?;
+        if runs.is_empty() {
+            continue;
+        }
+
+        update_status_message(ctx, &pr, checks_embed(&pr, runs)).await?;
+    }
+    Ok(())
+}
+
+/// Edits the PR's status message for its current head, or posts a new one
+/// after a push or if it was deleted.
+async fn update_status_message(
+    ctx: &Arc<Context>,
+    pr: &PullRequest,
+    embed: CreateEmbed,
+) -> Result<(), DiscussionError> {
+    let id = pr.number;
+    let repo = ENV_VARS.repo_slug();
+    let thread = find_pr_thread(ctx, id).await?;
+    revive(ctx, id, &thread).await?;
+    let channel = thread.id;
+
+    if let Some(status) = STATUS_MESSAGES
+        .get(&repo, id)
+        .filter(|status| status.sha == pr.head.sha)
+    {
+        let edit = EditMessage::new().embed(embed.clone());
+        let result = RetryPolicy::DISCORD
+            .run(&format!("Editing status message for PR #{id}"), || {
+                channel.edit_message(ctx, status.message, edit.clone())
+            })
+            .await;
+        match result {
+            Ok(_) => return Ok(()),
+            Err(err) if retry::is_not_found(&err) => {
+                info!("Status message for PR #{id} was deleted, posting a new one");
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    let message = CreateMessage::new().embed(embed);
+    let message = RetryPolicy::DISCORD
+        .run_create(&format!("Sending status message for PR #{id}"), || {
+            channel.send_message(ctx, message.clone())
+        })
+        .await?;
+    STATUS_MESSAGES.insert(&repo, id, pr.head.sha.clone(), message.id);
+    Ok(())
+}
+
+/// Forum tags mirroring the PR's labels, per `LABEL_TAGS`.
+pub fn label_tags(pr: &PullRequest) -> Vec<ForumTagId> {
+    pr.labels
+        .iter()
+        .flatten()
+        .filter_map(|label| ENV_VARS.label_tags.get(&label.name.to_lowercase()))
+        .copied()
+        .collect()
+}
+
+fn is_label_tag(tag: &ForumTagId) -> bool {
+    ENV_VARS.label_tags.values().any(|t| t == tag)
+}
+
+/// Orders tags by priority and cuts them to Discord's limit: the state tag,
+/// then tags moderators added by hand, then label tags, which can be restored
+/// from GitHub at any time.
+fn merge_tags(
+    state: ForumTagId,
+    applied: &[ForumTagId],
+    labels: Vec<ForumTagId>,
+) -> Vec<ForumTagId> {
+    let manual = applied
+        .iter()
+        .copied()
+        .filter(|t| !ENV_VARS.state_tags().contains(t) && !is_label_tag(t));
+
+    let mut tags = Vec::with_capacity(MAX_APPLIED_TAGS);
+    for tag in std::iter::once(state).chain(manual).chain(labels) {
+        if tags.len() == MAX_APPLIED_TAGS {
+            break;
+        }
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+/// Swaps the state tag and, if given, the label tags of a PR's forum post,
+/// keeping any other tags. Without a state tag the current one is kept.
+/// Returns whether anything was changed.
+pub async fn update_tags(
+    ctx: &Arc<Context>,
+    id: u64,
+    state: Option<ForumTagId>,
+    labels: Option<Vec<ForumTagId>>,
+) -> Result<bool, DiscussionError> {
+    let thread = find_pr_thread(ctx, id).await?;
+    let applied = thread.applied_tags.clone();
+    let state = state.or_else(|| {
+        applied
+            .iter()
+            .copied()
+            .find(|t| ENV_VARS.state_tags().contains(t))
+    });
+    let labels = labels.unwrap_or_else(|| applied.iter().copied().filter(is_label_tag).collect());
+
+    let tags = match state {
+        Some(state) => merge_tags(state, &applied, labels),
+        // A post without a state tag shouldn't happen, keep it that way rather than guess one
+        None => {
+            let mut tags = applied.clone();
+            tags.retain(|t| !is_label_tag(t));
+            tags.extend(labels);
+            tags.truncate(MAX_APPLIED_TAGS);
+            tags
+        }
+    };
+    if tags.len() == applied.len() && tags.iter().all(|t| applied.contains(t)) {
+        return Ok(false);
+    }
+
+    info!("Updating tags of PR #{id} from {applied:?} to {tags:?}");
+
+    // Unarchiving in the same edit, as Discord rejects edits to archived threads
+    let edit = EditThread::new().archived(false).applied_tags(tags);
+    RetryPolicy::DISCORD
+        .run(&format!("Editing thread for PR #{id}"), || {
+            thread.id.edit_thread(ctx, edit.clone())
+        })
+        .await?;
+    Ok(true)
+}
+
+/// Replaces the state tag of a PR's forum post.
+pub async fn apply_tag(
+    ctx: &Arc<Context>,
+    id: u64,
+    tag: ForumTagId,
+) -> Result<(), DiscussionError> {
+    update_tags(ctx, id, Some(tag), None).await?;
+    Ok(())
+}
+
+/// Syncs the label tags of a PR's forum post after its labels changed.
+pub async fn labels_changed(ctx: &Arc<Context>, pr: &PullRequest) -> Result<(), DiscussionError> {
+    update_tags(ctx, pr.number, None, Some(label_tags(pr))).await?;
+    Ok(())
+}
+
+pub async fn send_message(
+    ctx: &Arc<Context>,
+    id: u64,
+    message: CreateMessage,
+) -> Result<Message, DiscussionError> {
+    let thread = find_pr_thread(ctx, id).await?;
+    revive(ctx, id, &thread).await?;
+
+    let message = RetryPolicy::DISCORD
+        .run_create(&format!("Sending message for PR #{id}"), || {
+            thread.id.send_message(ctx, message.clone())
+        })
+        .await?;
+    Ok(message)
+}
+
+/// Sends text relayed from GitHub, split to fit Discord's limit and without
+/// pinging anyone it mentions except `pings`, who are cc'd at the end.
+/// Replies to `reference` if given and returns the first message.
+pub async fn send_relayed(
+    ctx: &Arc<Context>,
+    id: u64,
+    content: &str,
+    reference: Option<MessageReference>,
+    pings: &[UserId],
+) -> Result<Message, DiscussionError> {
+    let mut content = content.to_string();
+    if !pings.is_empty() {
+        let mentions = pings
+            .iter()
+            .map(|u| format!("<@{u}>"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        content.push_str(&format!("\n-# cc {mentions}"));
+    }
+
+    let mut first = None;
+    for chunk in markdown::split(&content, markdown::MAX_MESSAGE_LEN) {
+        let mut message = CreateMessage::new()
+            .content(chunk)
+            .allowed_mentions(CreateAllowedMentions::new().users(pings.iter().copied()));
+        if first.is_none()
+            && let Some(reference) = reference.clone()
+        {
+            message = message.reference_message(reference);
+        }
+
+        let sent = send_message(ctx, id, message).await?;
+        first.get_or_insert(sent);
+    }
+    first.ok_or(DiscussionError::EmptyMessage(id))
+}
+
+fn roster_line(login: &str, state: &ReviewState) -> Option<String> {
+    let mention = accounts::mention(login);
+    match state {
+        ReviewState::Approved => Some(format!("✅ {mention} - approved")),
+        ReviewState::ChangesRequested => Some(format!("❌ {mention} - changes requested")),
+        ReviewState::Dismissed => Some(format!("➖ {mention} - review dismissed")),
+        _ => None,
+    }
+}
+
+/// The pinned reviewer roster of a PR's forum post, if it has one yet.
+async fn find_roster(
+    ctx: &Arc<Context>,
+    id: u64,
+    channel: ChannelId,
+) -> Result<Option<MessageId>, DiscussionError> {
+    let bot = ctx.cache.current_user().id;
+    let pins = RetryPolicy::DISCORD
+        .run(&format!("Fetching pins for PR #{id}"), || channel.pins(ctx))
+        .await?;
+
+    Ok(pins
+        .iter()
+        .find(|m| {
+            m.author.id == bot
+                && m.embeds
+                    .first()
+                    .is_some_and(|e| e.title.as_deref() == Some(ROSTER_TITLE))
+        })
+        .map(|m| m.id))
+}
+
+/// Updates the pinned roster listing the PR's requested reviewers and the
+/// state of everyone who reviewed it, creating it on the first reviewer.
+pub async fn update_reviewers(ctx: &Arc<Context>, pr: &PullRequest) -> Result<(), DiscussionError> {
+    let id = pr.number;
+    let requested = pr
+        .requested_reviewers
+        .iter()
+        .flatten()
+        .map(|u| u.login.as_str())
+        .collect::<Vec<_>>();
+
+    let mut lines = requested
+        .iter()
+        .map(|login| format!("⏳ {} - review requested", accounts::mention(login)))
+        .collect::<Vec<_>>();
+    lines.extend(
+        pr.requested_teams
+            .iter()
+            .flatten()
+            .map(|t| format!("⏳ team **{}** - review requested", t.name)),
+    );
+
+    let mut reviews = approvals::latest_reviews(&github_client(), id)
+        .await?
+        .into_iter()
+        .collect::<Vec<_>>();
+    reviews.sort_by_key(|(login, _)| login.to_lowercase());
+    // Re-requested reviewers are waiting on a new review
+    lines.extend(
+        reviews
+            .iter()
+            .filter(|(login, _)| !requested.iter().any(|r| r.eq_ignore_ascii_case(login)))
+            .filter_map(|(login, review)| roster_line(login, &review.state)),
+    );
+
+    let thread = find_pr_thread(ctx, id).await?;
+    let channel = thread.id;
+    let roster = find_roster(ctx, id, channel).await?;
+    if roster.is_none() && lines.is_empty() {
+        return Ok(());
+    }
+
+    let description = if lines.is_empty() {
+        "No reviewers yet.".to_string()
+    } else {
+        lines.join("\n")
     };
+    let embed = CreateEmbed::new()
+        .title(ROSTER_TITLE)
+        .description(markdown::truncate(&description, MAX_EMBED_DESCRIPTION_LEN))
+        .color(COLOR_OPEN);
 
-    if let Err(err) = channel.send_message(ctx, message).await {
-        error!("Failed sending message for PR #{id}: {err}");
+    revive(ctx, id, &thread).await?;
+    match roster {
+        Some(message) => {
+            let edit = EditMessage::new().embed(embed);
+            RetryPolicy::DISCORD
+                .run(&format!("Editing reviewer roster for PR #{id}"), || {
+                    channel.edit_message(ctx, message, edit.clone())
+                })
+                .await?;
+        }
+        None => {
+            let message = CreateMessage::new().embed(embed);
+            let message = RetryPolicy::DISCORD
+                .run_create(&format!("Sending reviewer roster for PR #{id}"), || {
+                    channel.send_message(ctx, message.clone())
+                })
+                .await?;
+            RetryPolicy::DISCORD
+                .run(&format!("Pinning reviewer roster for PR #{id}"), || {
+                    message.pin(ctx)
+                })
+                .await?;
+        }
     }
+    Ok(())
+}
+
+/// Merges the PR if it has a merge queued with `/merge auto` and nothing blocks
+/// it anymore, posting the outcome in its forum post.
+pub async fn try_auto_merge(ctx: &Arc<Context>, id: u64) -> Result<(), DiscussionError> {
+    let repo = ENV_VARS.repo_slug();
+    let Some(pending) = AUTO_MERGES.get(&repo, id) else {
+        return Ok(());
+    };
+
+    let client = github_client();
+    let readiness = merge_checks::check(&client, id).await?;
+    if readiness.pr.head.sha != pending.sha {
+        return cancel_auto_merge(ctx, id, "new commits were pushed").await;
+    }
+    if readiness.blockers.contains(&Blocker::Closed) {
+        return cancel_auto_merge(ctx, id, "the pull request was closed").await;
+    }
+    if !readiness.is_ready() {
+        return Ok(());
+    }
+
+    // Removed first so a failing merge isn't retried on every later event, and
+    // only whoever removes it merges when the reconciliation runs alongside
+    let Some(pending) = AUTO_MERGES.remove(&repo, id) else {
+        return Ok(());
+    };
+    info!("Auto-merging PR #{id}");
+    let merged = merge_checks::merge(
+        &client,
+        id,
+        &pending.sha,
+        pending.method.into(),
+        pending.title,
+        format!("Auto-merged on Discord for {}", pending.attribution),
+    )
+    .await;
+    let content = match merged {
+        Ok(_) => format!("Pull request #{id} was approved and passed its checks, auto-merging!"),
+        Err(err) => format!("Auto-merging pull request #{id} failed: {err}"),
+    };
+    send_relayed(ctx, id, &content, None, &[pending.requested_by]).await?;
+    Ok(())
+}
+
+/// Drops the PR's queued auto-merge, if any, and lets whoever queued it know why.
+pub async fn cancel_auto_merge(
+    ctx: &Arc<Context>,
+    id: u64,
+    reason: &str,
+) -> Result<(), DiscussionError> {
+    let Some(pending) = AUTO_MERGES.remove(&ENV_VARS.repo_slug(), id) else {
+        return Ok(());
+    };
+
+    info!("Cancelled auto-merge of PR #{id}: {reason}");
+    send_relayed(
+        ctx,
+        id,
+        &format!("Auto-merge of pull request #{id} was cancelled, {reason}"),
+        None,
+        &[pending.requested_by],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Archives the forum post of a PR, used after it was merged or closed.
+pub async fn archive(ctx: &Arc<Context>, id: u64) -> Result<(), DiscussionError> {
+    let channel = find_pr_thread(ctx, id).await?.id;
+
+    RetryPolicy::DISCORD
+        .run(&format!("Archiving thread for PR #{id}"), || {
+            channel.edit_thread(ctx, EditThread::new().archived(true))
+        })
+        .await?;
+    Ok(())
+}
+
+/// Unarchives the forum post of a PR if Discord auto-archived it in the meantime.
+async fn revive(ctx: &Arc<Context>, id: u64, thread: &GuildChannel) -> Result<(), DiscussionError> {
+    let archived = thread.thread_metadata.is_some_and(|m| m.archived);
+    if !archived {
+        return Ok(());
+    }
+
+    info!("Unarchiving forum post for PR #{id}");
+    RetryPolicy::DISCORD
+        .run(&format!("Unarchiving thread for PR #{id}"), || {
+            thread
+                .id
+                .edit_thread(ctx, EditThread::new().archived(false))
+        })
+        .await?;
+    Ok(())
 }
 
 pub fn find_pr_from_post(channel: GuildChannel) -> Option<u64> {
-    let name_stripped = channel.name().strip_prefix("#")?;
+    pr_number_from_name(channel.name())
+}
+
+fn pr_number_from_name(name: &str) -> Option<u64> {
+    let name_stripped = name.strip_prefix("#")?;
     let words = name_stripped.split_whitespace().collect::<Vec<&str>>();
 
     words.first().and_then(|word| word.parse::<u64>().ok())
 }
 
-async fn find_pr_post(ctx: &Arc<Context>, id: u64) -> Option<ChannelId> {
-    let threads = match ENV_VARS.guild.get_active_threads(ctx).await {
-        Ok(data) => data,
-        Err(err) => {
-            error!("Failed fetching active threads: {err}");
-            return None;
-        }
+/// The mapped forum post of a PR, forgetting the mapping if the thread was deleted.
+async fn mapped_thread(
+    ctx: &Arc<Context>,
+    id: u64,
+) -> Result<Option<GuildChannel>, DiscussionError> {
+    let repo = ENV_VARS.repo_slug();
+    let Some(thread) = THREADS.get(&repo, id) else {
+        return Ok(None);
     };
 
-    for thread in threads.threads {
-        if thread.parent_id.is_none_or(|id| id != ENV_VARS.pr_channel) {
-            continue;
+    let result = RetryPolicy::DISCORD
+        .run(&format!("Fetching thread for PR #{id}"), || {
+            thread.to_channel(ctx)
+        })
+        .await;
+    match result {
+        Ok(channel) => Ok(channel.guild()),
+        Err(err) if retry::is_not_found(&err) => {
+            warn!("Forum post for PR #{id} was deleted, forgetting it");
+            THREADS.remove(&repo, id);
+            Ok(None)
         }
+        Err(err) => Err(err.into()),
+    }
+}
 
-        if !thread.name().starts_with(&format!("#{id}")) {
-            continue;
+/// Looks up and fetches the forum post of a PR, `None` if it has none yet.
+/// Callers work with the fetched thread rather than fetching it again.
+async fn find_thread(ctx: &Arc<Context>, id: u64) -> Result<Option<GuildChannel>, DiscussionError> {
+    if let Some(thread) = mapped_thread(ctx, id).await? {
+        return Ok(Some(thread));
+    }
+
+    let Some(thread) = scan_pr_posts(ctx, id).await? else {
+        return Ok(None);
+    };
+    info!("Found unmapped forum post for PR #{id} by name");
+    THREADS.insert(&ENV_VARS.repo_slug(), id, thread.id);
+    Ok(Some(thread))
+}
+
+async fn find_pr_thread(ctx: &Arc<Context>, id: u64) -> Result<GuildChannel, DiscussionError> {
+    find_thread(ctx, id)
+        .await?
+        .ok_or(DiscussionError::MissingPost(id))
+}
+
+/// Looks up the forum post of a PR, `None` if it has none yet.
+pub async fn find_post(ctx: &Arc<Context>, id: u64) -> Result<Option<ChannelId>, DiscussionError> {
+    Ok(find_thread(ctx, id).await?.map(|thread| thread.id))
+}
+
+/// Fallback for threads created before the mapping existed: matches thread names
+/// against `#N`, first in the active threads and then the forum's archived ones.
+async fn scan_pr_posts(
+    ctx: &Arc<Context>,
+    id: u64,
+) -> Result<Option<GuildChannel>, DiscussionError> {
+    let matches = |thread: &GuildChannel| {
+        thread.parent_id == Some(ENV_VARS.pr_channel)
+            && pr_number_from_name(thread.name()) == Some(id)
+    };
+
+    let active = RetryPolicy::DISCORD
+        .run("Fetching active threads", || {
+            ENV_VARS.guild.get_active_threads(ctx)
+        })
+        .await?;
+    if let Some(thread) = active.threads.into_iter().find(matches) {
+        return Ok(Some(thread));
+    }
+
+    let mut before = None;
+    for _ in 0..MAX_ARCHIVE_PAGES {
+        let archived = RetryPolicy::DISCORD
+            .run("Fetching archived threads", || {
+                ENV_VARS
+                    .pr_channel
+                    .get_archived_public_threads(ctx, before, Some(100))
+            })
+            .await?;
+        if let Some(thread) = archived.threads.iter().find(|t| matches(t)) {
+            return Ok(Some(thread.clone()));
         }
 
-        return Some(thread.id);
+        let oldest = archived
+            .threads
+            .iter()
+            .filter_map(|t| t.thread_metadata?.archive_timestamp)
+            .map(|t| t.unix_timestamp() as u64)
+            .min();
+        if !archived.has_more || oldest.is_none() || oldest == before {
+            break;
+        }
+        before = oldest;
     }
 
-    None
+    Ok(None)
 }
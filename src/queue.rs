@@ -0,0 +1,252 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tracing::{error, warn};
+
+use crate::{Event, json_store::write_atomic};
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum LogEntry {
+    Push { seq: u64, event: Box<Event> },
+    Ack { seq: u64 },
+}
+
+struct QueueState {
+    pending: VecDeque<(u64, Event)>,
+    next_seq: u64,
+    log: File,
+}
+
+/// Append-only, file-backed queue between the webhook and the Discord main loop.
+///
+/// Every pushed event and every acknowledgement is appended to a JSON lines log,
+/// which is replayed on startup so unacknowledged events survive restarts. The
+/// log is truncated whenever the queue drains.
+pub struct EventQueue {
+    path: PathBuf,
+    state: Mutex<QueueState>,
+    notify: Notify,
+}
+
+impl EventQueue {
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+
+        let mut pending = VecDeque::<(u64, Event)>::new();
+        let mut next_seq = 0;
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        for line in data.split(|b| *b == b'\n').filter(|l| !l.is_empty()) {
+            match serde_json::from_slice::<LogEntry>(line) {
+                Ok(LogEntry::Push { seq, event }) => {
+                    next_seq = next_seq.max(seq + 1);
+                    pending.push_back((seq, *event));
+                }
+                Ok(LogEntry::Ack { seq }) => pending.retain(|(s, _)| *s != seq),
+                Err(err) => warn!("Skipping corrupt event queue entry: {err}"),
+            }
+        }
+
+        // Compact the log down to what is still pending, without ever leaving
+        // it truncated in between
+        let mut compacted = Vec::new();
+        for (seq, event) in &pending {
+            write_entry(
+                &mut compacted,
+                &LogEntry::Push {
+                    seq: *seq,
+                    event: Box::new(event.clone()),
+                },
+            )?;
+        }
+        write_atomic(&path, &compacted)?;
+        let log = OpenOptions::new().append(true).open(&path)?;
+
+        Ok(Self {
+            path,
+            state: Mutex::new(QueueState {
+                pending,
+                next_seq,
+                log,
+            }),
+            notify: Notify::new(),
+        })
+    }
+
+    pub fn push(&self, event: Event) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let seq = state.next_seq;
+
+        write_entry(
+            &mut state.log,
+            &LogEntry::Push {
+                seq,
+                event: Box::new(event.clone()),
+            },
+        )?;
+        state.log.sync_data()?;
+
+        state.next_seq += 1;
+        state.pending.push_back((seq, event));
+        drop(state);
+
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    /// Waits for the oldest unacknowledged event. It stays in the queue until
+    /// [`EventQueue::ack`] is called with its sequence number.
+    pub async fn next(&self) -> (u64, Event) {
+        loop {
+            let notified = self.notify.notified();
+            if let Some(entry) = self.state.lock().unwrap().pending.front() {
+                return entry.clone();
+            }
+            notified.await;
+        }
+    }
+
+    pub fn ack(&self, seq: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.pending.retain(|(s, _)| *s != seq);
+
+        let result = if state.pending.is_empty() {
+            state.log.set_len(0)
+        } else {
+            write_entry(&mut state.log, &LogEntry::Ack { seq })
+        };
+        if let Err(err) = result {
+            error!(
+                "Failed acknowledging event in {}: {err}",
+                self.path.display()
+            );
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn write_entry(log: &mut impl Write, entry: &LogEntry) -> io::Result<()> {
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    log.write_all(&line)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, path::Path};
+
+    use super::*;
+
+    fn queue_path(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("event-queue-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn event(sha: &str) -> Event {
+        Event::ChecksUpdated(sha.to_string(), vec![1])
+    }
+
+    fn pending_shas(queue: &EventQueue) -> Vec<String> {
+        queue
+            .state
+            .lock()
+            .unwrap()
+            .pending
+            .iter()
+            .map(|(_, event)| match event {
+                Event::ChecksUpdated(sha, _) => sha.clone(),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    fn append(path: &Path, data: &[u8]) {
+        OpenOptions::new()
+            .append(true)
+            .open(path)
+            .unwrap()
+            .write_all(data)
+            .unwrap();
+    }
+
+    #[test]
+    fn reopening_keeps_unacknowledged_events() {
+        let path = queue_path("reopen.jsonl");
+        let queue = EventQueue::open(&path).unwrap();
+        queue.push(event("a")).unwrap();
+        queue.push(event("b")).unwrap();
+        queue.push(event("c")).unwrap();
+        queue.ack(1);
+        drop(queue);
+
+        let queue = EventQueue::open(&path).unwrap();
+        assert_eq!(pending_shas(&queue), ["a", "c"]);
+
+        // Sequence numbers keep counting up after a reopen
+        queue.push(event("d")).unwrap();
+        queue.ack(0);
+        drop(queue);
+        let queue = EventQueue::open(&path).unwrap();
+        assert_eq!(pending_shas(&queue), ["c", "d"]);
+    }
+
+    #[test]
+    fn draining_empties_the_log() {
+        let path = queue_path("drain.jsonl");
+        let queue = EventQueue::open(&path).unwrap();
+        queue.push(event("a")).unwrap();
+        queue.ack(0);
+        assert!(queue.is_empty());
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn replay_skips_partially_written_last_line() {
+        let path = queue_path("partial.jsonl");
+        let queue = EventQueue::open(&path).unwrap();
+        queue.push(event("a")).unwrap();
+        drop(queue);
+        append(&path, br#"{"push":{"seq":1,"event":{"ChecksUp"#);
+
+        let queue = EventQueue::open(&path).unwrap();
+        assert_eq!(pending_shas(&queue), ["a"]);
+        queue.push(event("b")).unwrap();
+        drop(queue);
+
+        let queue = EventQueue::open(&path).unwrap();
+        assert_eq!(pending_shas(&queue), ["a", "b"]);
+    }
+
+    #[test]
+    fn replay_skips_lines_that_arent_utf8() {
+        let path = queue_path("utf8.jsonl");
+        let queue = EventQueue::open(&path).unwrap();
+        queue.push(event("a")).unwrap();
+        drop(queue);
+        append(&path, b"\xff\xfe\n");
+
+        let queue = EventQueue::open(&path).unwrap();
+        assert_eq!(pending_shas(&queue), ["a"]);
+    }
+}
@@ -0,0 +1,237 @@
+use std::{env, fmt, sync::Arc, time::Duration};
+
+use octocrab::{
+    Octocrab,
+    models::pulls::{PullRequest, ReviewState},
+    params::{Direction, State, pulls::Sort},
+};
+use poise::serenity_prelude::{Context, ForumTagId};
+use tracing::{error, info};
+
+use crate::{
+    AUTO_MERGES, ENV_VARS, THREADS, approvals, github_client,
+    pr_discussion::{self, DiscussionError},
+};
+
+#[derive(Debug)]
+pub enum ReconcileError {
+    GitHub(octocrab::Error),
+    Discussion(DiscussionError),
+}
+
+impl fmt::Display for ReconcileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GitHub(err) => write!(f, "{err}"),
+            Self::Discussion(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for ReconcileError {}
+
+impl From<octocrab::Error> for ReconcileError {
+    fn from(err: octocrab::Error) -> Self {
+        Self::GitHub(err)
+    }
+}
+
+impl From<DiscussionError> for ReconcileError {
+    fn from(err: DiscussionError) -> Self {
+        Self::Discussion(err)
+    }
+}
+
+/// The state tag a PR's forum post should have according to GitHub.
+pub async fn expected_tag(
+    client: &Octocrab,
+    pr: &PullRequest,
+) -> Result<ForumTagId, octocrab::Error> {
+    let tag = if pr.merged_at.is_some() {
+        ENV_VARS.tag_merged
+    } else if pr.closed_at.is_some() {
+        ENV_VARS.tag_closed
+    } else if pr.draft.unwrap_or_default() {
+        ENV_VARS.tag_draft
+    } else {
+        let reviews = approvals::latest_reviews(client, pr.number).await?;
+        if reviews
+            .values()
+            .any(|r| r.state == ReviewState::ChangesRequested)
+        {
+            ENV_VARS.tag_changes_requested
+        } else if approvals::status(client, pr, &reviews).await?.is_met() {
+            ENV_VARS.tag_approved
+        } else {
+            ENV_VARS.tag_review_needed
+        }
+    };
+    Ok(tag)
+}
+
+/// Applies the expected state and label tags if the forum post doesn't have them.
+/// Returns whether anything was changed.
+pub async fn reconcile_tag(
+    ctx: &Arc<Context>,
+    client: &Octocrab,
+    pr: &PullRequest,
+) -> Result<bool, ReconcileError> {
+    let expected = expected_tag(client, pr).await?;
+    let labels = pr_discussion::label_tags(pr);
+    Ok(pr_discussion::update_tags(ctx, pr.number, Some(expected), Some(labels)).await?)
+}
+
+async fn backfill_pr(
+    ctx: &Arc<Context>,
+    client: &Octocrab,
+    pr: PullRequest,
+) -> Result<(), ReconcileError> {
+    if pr_discussion::find_post(ctx, pr.number).await?.is_none() {
+        info!("Creating missing forum post for PR #{}", pr.number);
+        pr_discussion::pr_created(ctx, pr.clone()).await?;
+    }
+
+    reconcile_tag(ctx, client, &pr).await?;
+    Ok(())
+}
+
+/// Corrects the tag of a PR closed while the bot was down, archiving the post
+/// like the close event would have.
+async fn backfill_closed_pr(
+    ctx: &Arc<Context>,
+    client: &Octocrab,
+    pr: &PullRequest,
+) -> Result<(), ReconcileError> {
+    // Updating the tags unarchives the post
+    if reconcile_tag(ctx, client, pr).await? && ENV_VARS.archive_closed {
+        pr_discussion::archive(ctx, pr.number).await?;
+    }
+    Ok(())
+}
+
+/// How many of the most recently updated closed PRs are checked, enough to
+/// cover ones merged or closed while the bot was down.
+const RECENTLY_CLOSED: u8 = 100;
+
+async fn open_prs(client: &Octocrab) -> Result<Vec<PullRequest>, octocrab::Error> {
+    let page = client
+        .pulls(&ENV_VARS.repo_owner, &ENV_VARS.repo)
+        .list()
+        .state(State::Open)
+        .per_page(100)
+        .send()
+        .await?;
+    client.all_pages(page).await
+}
+
+/// Recently closed PRs that have a forum post.
+async fn recently_closed_prs(client: &Octocrab) -> Result<Vec<PullRequest>, octocrab::Error> {
+    let repo = ENV_VARS.repo_slug();
+    let page = client
+        .pulls(&ENV_VARS.repo_owner, &ENV_VARS.repo)
+        .list()
+        .state(State::Closed)
+        .sort(Sort::Updated)
+        .direction(Direction::Descending)
+        .per_page(RECENTLY_CLOSED)
+        .send()
+        .await?;
+
+    Ok(page
+        .items
+        .into_iter()
+        .filter(|pr| THREADS.get(&repo, pr.number).is_some())
+        .collect())
+}
+
+/// Creates forum posts for open PRs that were opened while the bot was down
+/// and corrects the tags of existing ones, including recently closed PRs.
+pub async fn backfill(ctx: &Arc<Context>) {
+    let client = github_client();
+    let prs = match open_prs(&client).await {
+        Ok(prs) => prs,
+        Err(err) => {
+            error!("Failed listing open PRs for backfill: {err}");
+            return;
+        }
+    };
+
+    info!("Backfilling {} open PR(s)", prs.len());
+    for pr in prs {
+        let id = pr.number;
+        if let Err(err) = backfill_pr(ctx, &client, pr).await {
+            error!("Failed backfilling PR #{id}: {err}");
+        }
+    }
+
+    let closed = match recently_closed_prs(&client).await {
+        Ok(prs) => prs,
+        Err(err) => {
+            error!("Failed listing closed PRs for backfill: {err}");
+            return;
+        }
+    };
+    for pr in closed {
+        let id = pr.number;
+        if let Err(err) = backfill_closed_pr(ctx, &client, &pr).await {
+            error!("Failed backfilling PR #{id}: {err}");
+        }
+    }
+}
+
+/// Compares the forum posts of open and recently closed PRs with GitHub and
+/// fixes any tag drift. Older posts were already settled when their PR closed.
+/// Queued auto-merges are retried too.
+pub async fn reconcile_all(ctx: &Arc<Context>) {
+    let client = github_client();
+    let repo = ENV_VARS.repo_slug();
+    let prs = match open_prs(&client).await {
+        Ok(open) => recently_closed_prs(&client).await.map(|closed| {
+            open.into_iter()
+                .filter(|pr| THREADS.get(&repo, pr.number).is_some())
+                .chain(closed)
+                .collect::<Vec<_>>()
+        }),
+        Err(err) => Err(err),
+    };
+    let prs = match prs {
+        Ok(prs) => prs,
+        Err(err) => {
+            error!("Failed listing PRs for reconciliation: {err}");
+            return;
+        }
+    };
+
+    let mut corrected = 0;
+    for pr in prs {
+        match reconcile_tag(ctx, &client, &pr).await {
+            Ok(true) => corrected += 1,
+            Ok(false) => {}
+            Err(err) => error!("Failed reconciling PR #{}: {err}", pr.number),
+        }
+    }
+
+    info!("Reconciliation finished, corrected {corrected} PR(s)");
+
+    // Catches auto-merges whose last blocker went away without an event for it
+    for id in AUTO_MERGES.ids(&repo) {
+        if let Err(err) = pr_discussion::try_auto_merge(ctx, id).await {
+            error!("Failed auto-merging PR #{id}: {err}");
+        }
+    }
+}
+
+/// Runs [`reconcile_all`] every `RECONCILE_INTERVAL_SECS` (default 1 hour).
+pub async fn run_periodic(ctx: Arc<Context>) {
+    let secs = env::var("RECONCILE_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(3600);
+    let period = Duration::from_secs(secs);
+
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    loop {
+        interval.tick().await;
+        reconcile_all(&ctx).await;
+    }
+}
@@ -0,0 +1,127 @@
+use std::sync::LazyLock;
+
+use octocrab::models::issues::Comment;
+use poise::serenity_prelude::{ChannelId, Context, Message, ReactionType, User};
+use regex::Regex;
+use tracing::{error, info};
+
+use crate::{ENV_VARS, THREADS, accounts, github_client, pr_discussion};
+
+/// `@login` or `@org/team` not inside a word (like an email) or code span.
+static GITHUB_MENTION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(^|[^\w`])@([A-Za-z0-9][A-Za-z0-9-]*(?:/[A-Za-z0-9_.-]+)?)").unwrap()
+});
+
+/// Hidden marker on comments posted from Discord, so the webhook doesn't
+/// relay them straight back into the thread.
+pub const RELAYED_COMMENT_MARKER: &str = "<!-- relayed from discord -->";
+
+pub fn is_relayed(body: &str) -> bool {
+    body.contains(RELAYED_COMMENT_MARKER)
+}
+
+/// The PR whose forum post a channel is, if any.
+pub async fn pr_of_channel(ctx: &Context, channel: ChannelId) -> Option<u64> {
+    if let Some(id) = THREADS.pr_of(&ENV_VARS.repo_slug(), channel) {
+        return Some(id);
+    }
+
+    let channel = channel.to_channel(ctx).await.ok()?.guild()?;
+    if channel.parent_id != Some(ENV_VARS.pr_channel) {
+        return None;
+    }
+    pr_discussion::find_pr_from_post(channel)
+}
+
+/// Wraps `@name`s in code spans, Discord names would ping whoever has that
+/// login on GitHub.
+fn escape_mentions(text: &str) -> String {
+    GITHUB_MENTION.replace_all(text, "$1`@$2`").into_owned()
+}
+
+/// Posts a Discord message as an issue comment on the PR, attributed to its author.
+pub async fn post_comment(id: u64, author: &User, text: &str) -> Result<Comment, octocrab::Error> {
+    let body = format!(
+        "**{}** on Discord:\n\n{}\n\n{RELAYED_COMMENT_MARKER}",
+        accounts::attribution(author),
+        escape_mentions(text)
+    );
+    github_client()
+        .issues(&ENV_VARS.repo_owner, &ENV_VARS.repo)
+        .create_comment(id, body)
+        .await
+}
+
+/// Relays messages in PR threads starting with `RELAY_MARKER` by members with
+/// `RELAY_ROLE`, if `RELAY_COMMENTS` is enabled. Reacts to the message with
+/// whether it worked.
+pub async fn handle_message(ctx: &Context, message: &Message) {
+    if !ENV_VARS.relay_comments || message.author.bot {
+        return;
+    }
+    let Some(text) = message.content.strip_prefix(&ENV_VARS.relay_marker) else {
+        return;
+    };
+    if text.trim().is_empty() {
+        return;
+    }
+    let Some(id) = pr_of_channel(ctx, message.channel_id).await else {
+        return;
+    };
+    if !message
+        .author
+        .has_role(ctx, ENV_VARS.guild, ENV_VARS.relay_role)
+        .await
+        .unwrap_or_default()
+    {
+        return;
+    }
+
+    // Resolves mentions to names, raw IDs mean nothing on GitHub
+    let text = message.content_safe(ctx);
+    let text = text
+        .strip_prefix(&ENV_VARS.relay_marker)
+        .unwrap_or(&text)
+        .trim();
+
+    let reaction = match post_comment(id, &message.author, text).await {
+        Ok(_) => {
+            info!("Relayed message by {} to PR #{id}", message.author.name);
+            '✅'
+        }
+        Err(err) => {
+            error!("Failed relaying message to PR #{id}: {err}");
+            '❌'
+        }
+    };
+    if let Err(err) = message
+        .react(ctx, ReactionType::Unicode(reaction.to_string()))
+        .await
+    {
+        error!("Failed reacting to relayed message: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_mentions() {
+        assert_eq!(escape_mentions("@someone"), "`@someone`");
+        assert_eq!(
+            escape_mentions("thanks @a-user and @org/team!"),
+            "thanks `@a-user` and `@org/team`!"
+        );
+    }
+
+    #[test]
+    fn leaves_emails_and_code_alone() {
+        assert_eq!(
+            escape_mentions("mail me@example.com"),
+            "mail me@example.com"
+        );
+        assert_eq!(escape_mentions("`@already` quoted"), "`@already` quoted");
+        assert_eq!(escape_mentions("just an @ sign"), "just an @ sign");
+    }
+}
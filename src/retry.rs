@@ -0,0 +1,107 @@
+use std::{future::Future, time::Duration};
+
+use poise::serenity_prelude::{Error as SerenityError, HttpError, StatusCode};
+use tracing::warn;
+
+/// Exponential backoff for Discord API calls.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub const DISCORD: Self = Self {
+        max_attempts: 5,
+        base_delay: Duration::from_millis(500),
+        max_delay: Duration::from_secs(30),
+    };
+
+    fn delay(&self, attempt: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay)
+    }
+
+    /// Runs `op` until it succeeds, fails with a non-transient error or runs
+    /// out of attempts.
+    pub async fn run<T, F, Fut>(&self, what: &str, op: F) -> Result<T, SerenityError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, SerenityError>>,
+    {
+        self.run_while(what, op, is_transient).await
+    }
+
+    /// Like [`run`](Self::run) for calls that create something, e.g. a message.
+    /// Those are only retried if Discord certainly didn't act on them, as a
+    /// timed out request may well have gone through.
+    pub async fn run_create<T, F, Fut>(&self, what: &str, op: F) -> Result<T, SerenityError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, SerenityError>>,
+    {
+        self.run_while(what, op, is_unsent).await
+    }
+
+    async fn run_while<T, F, Fut>(
+        &self,
+        what: &str,
+        mut op: F,
+        retryable: fn(&SerenityError) -> bool,
+    ) -> Result<T, SerenityError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, SerenityError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match op().await {
+                Ok(value) => return Ok(value),
+                Err(err) if attempt < self.max_attempts && retryable(&err) => {
+                    let delay = self.delay(attempt);
+                    warn!(
+                        "{what} failed (attempt {attempt}/{}), retrying in {delay:?}: {err}",
+                        self.max_attempts
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Server errors, rate limits and connection failures are worth retrying,
+/// anything else (missing permissions, unknown channel, ...) will fail again.
+pub fn is_transient(err: &SerenityError) -> bool {
+    match err {
+        SerenityError::Http(HttpError::UnsuccessfulRequest(res)) => {
+            res.status_code.is_server_error() || res.status_code == StatusCode::TOO_MANY_REQUESTS
+        }
+        SerenityError::Http(HttpError::Request(_)) | SerenityError::Io(_) => true,
+        _ => false,
+    }
+}
+
+/// Rate limited or never connected, so the request certainly wasn't acted on.
+pub fn is_unsent(err: &SerenityError) -> bool {
+    match err {
+        SerenityError::Http(HttpError::UnsuccessfulRequest(res)) => {
+            res.status_code == StatusCode::TOO_MANY_REQUESTS
+        }
+        SerenityError::Http(HttpError::Request(err)) => err.is_connect(),
+        _ => false,
+    }
+}
+
+/// Whatever was asked for doesn't exist (anymore), e.g. a deleted thread or message.
+pub fn is_not_found(err: &SerenityError) -> bool {
+    matches!(
+        err,
+        SerenityError::Http(HttpError::UnsuccessfulRequest(res))
+            if res.status_code == StatusCode::NOT_FOUND
+    )
+}
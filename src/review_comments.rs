@@ -0,0 +1,26 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use poise::serenity_prelude::MessageId;
+
+use crate::json_store::{JsonStore, key};
+
+/// Maps `owner/repo#comment` of the first comment of a GitHub review thread to
+/// the Discord message it was relayed as, so replies can reference it.
+pub struct ReviewCommentStore(JsonStore<HashMap<String, MessageId>>);
+
+impl ReviewCommentStore {
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        Self(JsonStore::load(path))
+    }
+
+    pub fn get(&self, repo: &str, comment: u64) -> Option<MessageId> {
+        self.0.lock().get(&key(repo, comment)).copied()
+    }
+
+    pub fn insert(&self, repo: &str, comment: u64, message: MessageId) {
+        let mut messages = self.0.lock();
+        if messages.insert(key(repo, comment), message) != Some(message) {
+            self.0.save(&messages);
+        }
+    }
+}
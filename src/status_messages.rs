@@ -0,0 +1,35 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use poise::serenity_prelude::MessageId;
+use serde::{Deserialize, Serialize};
+
+use crate::json_store::{JsonStore, key};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusMessage {
+    /// The commit the message reports on.
+    pub sha: String,
+    pub message: MessageId,
+}
+
+/// Maps `owner/repo#number` to the CI status message in that PR's forum post,
+/// so it can be edited in place instead of posting a new one per check.
+pub struct StatusMessageStore(JsonStore<HashMap<String, StatusMessage>>);
+
+impl StatusMessageStore {
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        Self(JsonStore::load(path))
+    }
+
+    pub fn get(&self, repo: &str, id: u64) -> Option<StatusMessage> {
+        self.0.lock().get(&key(repo, id)).cloned()
+    }
+
+    pub fn insert(&self, repo: &str, id: u64, sha: String, message: MessageId) {
+        let mut messages = self.0.lock();
+        let status = StatusMessage { sha, message };
+        if messages.insert(key(repo, id), status.clone()) != Some(status) {
+            self.0.save(&messages);
+        }
+    }
+}
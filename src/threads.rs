@@ -0,0 +1,41 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use poise::serenity_prelude::ChannelId;
+
+use crate::json_store::{JsonStore, key, parse_key};
+
+/// Maps `owner/repo#number` to the forum thread of that pull request.
+pub struct ThreadStore(JsonStore<HashMap<String, ChannelId>>);
+
+impl ThreadStore {
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        Self(JsonStore::load(path))
+    }
+
+    pub fn get(&self, repo: &str, id: u64) -> Option<ChannelId> {
+        self.0.lock().get(&key(repo, id)).copied()
+    }
+
+    /// The PR a thread is mapped to.
+    pub fn pr_of(&self, repo: &str, thread: ChannelId) -> Option<u64> {
+        self.0
+            .lock()
+            .iter()
+            .filter(|(_, t)| **t == thread)
+            .find_map(|(key, _)| parse_key(repo, key))
+    }
+
+    pub fn insert(&self, repo: &str, id: u64, thread: ChannelId) {
+        let mut threads = self.0.lock();
+        if threads.insert(key(repo, id), thread) != Some(thread) {
+            self.0.save(&threads);
+        }
+    }
+
+    pub fn remove(&self, repo: &str, id: u64) {
+        let mut threads = self.0.lock();
+        if threads.remove(&key(repo, id)).is_some() {
+            self.0.save(&threads);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    collections::VecDeque,
+    panic,
+    path::PathBuf,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::task;
+
+use super::WebhookError;
+use crate::{Event, json_store::JsonStore};
+
+/// How many deliveries are kept around for deduplication and inspection.
+const MAX_DELIVERIES: usize = 500;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "status", content = "detail", rename_all = "snake_case")]
+pub enum DeliveryOutcome {
+    Pending,
+    /// Pushed onto the event queue, the Discord side may still fail.
+    Queued,
+    Ignored,
+    Failed(String),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Delivery {
+    pub id: String,
+    pub kind: String,
+    pub received_at: u64,
+    /// What the delivery was queued as, see [`Event::summary`].
+    pub summary: Option<String>,
+    pub outcome: DeliveryOutcome,
+}
+
+/// Recent `X-GitHub-Delivery` IDs, persisted as JSON so redeliveries are
+/// still recognized after a restart.
+pub struct DeliveryStore(Arc<JsonStore<VecDeque<Delivery>>>);
+
+impl DeliveryStore {
+    /// Loads the store, marking deliveries left pending by a previous run as
+    /// failed so their redelivery is processed.
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let store = JsonStore::<VecDeque<Delivery>>::load(path);
+        let mut deliveries = store.lock();
+        let mut interrupted = false;
+        for delivery in deliveries.iter_mut() {
+            if delivery.outcome == DeliveryOutcome::Pending {
+                delivery.outcome = DeliveryOutcome::Failed("Interrupted by a restart".to_string());
+                interrupted = true;
+            }
+        }
+        if interrupted {
+            store.save(&deliveries);
+        }
+        drop(deliveries);
+
+        Self(Arc::new(store))
+    }
+
+    /// Runs `f` on a blocking thread, saving can take a while.
+    async fn with_store<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&JsonStore<VecDeque<Delivery>>) -> R + Send + 'static,
+    ) -> R {
+        let store = self.0.clone();
+        match task::spawn_blocking(move || f(&store)).await {
+            Ok(result) => result,
+            Err(err) => panic::resume_unwind(err.into_panic()),
+        }
+    }
+
+    /// Records a new delivery, returning `false` if it was already seen. Only
+    /// deliveries that failed may be retried, ones still pending are in flight.
+    pub async fn begin(&self, id: &str, kind: &str) -> bool {
+        let (id, kind) = (id.to_string(), kind.to_string());
+        self.with_store(move |store| {
+            let mut deliveries = store.lock();
+            if let Some(pos) = deliveries.iter().position(|d| d.id == id) {
+                if !matches!(deliveries[pos].outcome, DeliveryOutcome::Failed(_)) {
+                    return false;
+                }
+                deliveries.remove(pos);
+            }
+
+            let received_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            deliveries.push_back(Delivery {
+                id,
+                kind,
+                received_at,
+                summary: None,
+                outcome: DeliveryOutcome::Pending,
+            });
+            while deliveries.len() > MAX_DELIVERIES {
+                deliveries.pop_front();
+            }
+
+            store.save(&deliveries);
+            true
+        })
+        .await
+    }
+
+    pub async fn finish(&self, id: &str, event: Option<&Event>, result: Result<(), &WebhookError>) {
+        let id = id.to_string();
+        let summary = event.map(Event::summary);
+        let outcome = match result {
+            Ok(()) if event.is_some() => DeliveryOutcome::Queued,
+            Ok(()) => DeliveryOutcome::Ignored,
+            Err(err) => DeliveryOutcome::Failed(err.to_string()),
+        };
+        self.with_store(move |store| {
+            let mut deliveries = store.lock();
+            let Some(delivery) = deliveries.iter_mut().find(|d| d.id == id) else {
+                return;
+            };
+
+            delivery.outcome = outcome;
+            delivery.summary = summary;
+            store.save(&deliveries);
+        })
+        .await
+    }
+
+    /// Most recent deliveries first.
+    pub fn recent(&self) -> Vec<Delivery> {
+        self.0.lock().iter().rev().cloned().collect()
+    }
+}
@@ -0,0 +1,93 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use tracing::{error, trace};
+
+#[derive(Debug)]
+pub enum WebhookError {
+    MissingSignature,
+    InvalidSignature,
+    IncorrectSecret,
+    MissingHeader(&'static str),
+    InvalidHeader(&'static str),
+    BodyTooLarge,
+    BodyRead(axum::Error),
+    InvalidPayload(serde_json::Error),
+    UnexpectedPayload(&'static str),
+    EventQueue(std::io::Error),
+}
+
+impl WebhookError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::MissingSignature | Self::InvalidSignature | Self::IncorrectSecret => {
+                StatusCode::UNAUTHORIZED
+            }
+            Self::MissingHeader(_) | Self::InvalidHeader(_) | Self::BodyRead(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            Self::BodyTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::InvalidPayload(_) | Self::UnexpectedPayload(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            Self::EventQueue(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl std::fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingSignature => write!(f, "missing X-Hub-Signature-256 header"),
+            Self::InvalidSignature => write!(f, "invalid signature"),
+            Self::IncorrectSecret => write!(f, "incorrect secret"),
+            Self::MissingHeader(name) => write!(f, "missing {name} header"),
+            Self::InvalidHeader(name) => write!(f, "invalid {name} header"),
+            Self::BodyTooLarge => write!(f, "body too large"),
+            Self::BodyRead(err) => write!(f, "failed reading body: {err}"),
+            Self::InvalidPayload(err) => write!(f, "invalid payload: {err}"),
+            Self::UnexpectedPayload(msg) => write!(f, "unexpected payload: {msg}"),
+            Self::EventQueue(err) => write!(f, "failed queueing event: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for WebhookError {}
+
+impl From<axum::Error> for WebhookError {
+    fn from(err: axum::Error) -> Self {
+        let too_large = std::error::Error::source(&err)
+            .is_some_and(|source| source.is::<http_body_util::LengthLimitError>());
+        if too_large {
+            Self::BodyTooLarge
+        } else {
+            Self::BodyRead(err)
+        }
+    }
+}
+
+impl From<serde_json::Error> for WebhookError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::InvalidPayload(err)
+    }
+}
+
+impl From<std::io::Error> for WebhookError {
+    fn from(err: std::io::Error) -> Self {
+        Self::EventQueue(err)
+    }
+}
+
+impl IntoResponse for WebhookError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if status.is_server_error() {
+            error!("Webhook delivery failed: {self}");
+        } else {
+            trace!("Rejected webhook delivery: {self}");
+        }
+
+        (status, self.to_string()).into_response()
+    }
+}
@@ -0,0 +1,600 @@
+use std::{env, sync::Arc};
+
+use axum::{
+    Json, Router,
+    body::{Body, Bytes},
+    extract::{Path, State},
+    http::{HeaderMap, Request, StatusCode},
+    routing::{get, post},
+};
+use hmac::{Hmac, Mac};
+use octocrab::models::{
+    pulls::ReviewState,
+    webhook_events::{
+        WebhookEvent, WebhookEventPayload, WebhookEventType,
+        payload::{
+            CheckRunWebhookEventAction, CheckSuiteWebhookEventAction,
+            PullRequestReviewCommentWebhookEventAction, PullRequestReviewThreadWebhookEventAction,
+            PullRequestReviewWebhookEventAction, PullRequestReviewWebhookEventPayload,
+            PullRequestWebhookEventAction, PullRequestWebhookEventPayload,
+            WorkflowRunWebhookEventAction,
+        },
+    },
+};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+use tokio::net::TcpListener;
+use tracing::{error, info, trace, warn};
+
+use crate::{Event, is_contributor, json_store::state_path, relay, send_event};
+
+mod deliveries;
+mod error;
+
+pub use deliveries::{Delivery, DeliveryOutcome, DeliveryStore};
+pub use error::WebhookError;
+
+/// GitHub caps webhook payloads at 25 MB.
+const MAX_BODY_SIZE: usize = 25 * 1024 * 1024;
+
+type HandlerResult = Result<Option<Event>, WebhookError>;
+
+struct WebhookState {
+    secret: String,
+    deliveries: DeliveryStore,
+}
+
+async fn handle_pr_created(event: Box<PullRequestWebhookEventPayload>) -> HandlerResult {
+    let pr = event.pull_request;
+    info!(
+        "PR created: #{} - {:?} by {:?}",
+        event.number,
+        pr.clone().title,
+        pr.clone().user.map(|u| u.login)
+    );
+    Ok(Some(Event::PullRequestOpened(pr)))
+}
+
+async fn handle_pr_ready(event: Box<PullRequestWebhookEventPayload>) -> HandlerResult {
+    let pr = event.pull_request;
+    info!(
+        "PR ready for review: #{} - {:?} by {:?}",
+        event.number,
+        pr.clone().title,
+        pr.clone().user.map(|u| u.login)
+    );
+    Ok(Some(Event::PullRequestReady(pr)))
+}
+
+async fn handle_pr_closed(event: Box<PullRequestWebhookEventPayload>) -> HandlerResult {
+    let pr = event.pull_request;
+    if !pr.merged.unwrap_or_default() {
+        info!(
+            "PR closed: #{} - {:?} by {:?}",
+            event.number,
+            pr.clone().title,
+            pr.clone().user.map(|u| u.login)
+        );
+        Ok(Some(Event::PullRequestClosed(pr)))
+    } else {
+        info!(
+            "PR merged: #{} - {:?} by {:?}",
+            event.number,
+            pr.clone().title,
+            pr.clone().user.map(|u| u.login)
+        );
+        Ok(Some(Event::PullRequestMerged(pr)))
+    }
+}
+
+async fn handle_pr_drafted(event: Box<PullRequestWebhookEventPayload>) -> HandlerResult {
+    let pr = event.pull_request;
+    info!(
+        "PR drafted: #{} - {:?} by {:?}",
+        event.number,
+        pr.clone().title,
+        pr.clone().user.map(|u| u.login)
+    );
+    Ok(Some(Event::PullRequestDrafted(pr)))
+}
+
+async fn handle_pr_reopened(event: Box<PullRequestWebhookEventPayload>) -> HandlerResult {
+    let pr = event.pull_request;
+    info!(
+        "PR reopened: #{} - {:?} by {:?}",
+        event.number,
+        pr.clone().title,
+        pr.clone().user.map(|u| u.login)
+    );
+
+    if pr.draft.unwrap_or_default() {
+        Ok(Some(Event::PullRequestDrafted(pr)))
+    } else {
+        Ok(Some(Event::PullRequestReady(pr)))
+    }
+}
+
+async fn handle_pr_edited(event: Box<PullRequestWebhookEventPayload>) -> HandlerResult {
+    // The payload doesn't say what was edited, renaming is a no-op if the title didn't change
+    let pr = event.pull_request;
+    trace!("PR edited: #{} - {:?}", event.number, pr.clone().title);
+    Ok(Some(Event::PullRequestRenamed(pr)))
+}
+
+async fn handle_pr_labeled(event: Box<PullRequestWebhookEventPayload>) -> HandlerResult {
+    let pr = event.pull_request;
+    trace!(
+        "PR labels changed: #{} - {:?}",
+        event.number,
+        pr.clone().title
+    );
+    Ok(Some(Event::PullRequestLabeled(pr)))
+}
+
+async fn handle_pr_review_requested(event: Box<PullRequestWebhookEventPayload>) -> HandlerResult {
+    let requested = event.requested_reviewer.map(|u| u.login);
+    info!(
+        "PR review requested from {:?}/{:?}: #{}",
+        requested,
+        event.requested_team.as_ref().map(|t| &t.slug),
+        event.number
+    );
+    Ok(Some(Event::PullRequestReviewersChanged(
+        event.pull_request,
+        requested,
+    )))
+}
+
+async fn handle_pr_review_request_removed(
+    event: Box<PullRequestWebhookEventPayload>,
+) -> HandlerResult {
+    info!("PR review request removed: #{}", event.number);
+    Ok(Some(Event::PullRequestReviewersChanged(
+        event.pull_request,
+        None,
+    )))
+}
+
+async fn handle_pr_synchronized(event: Box<PullRequestWebhookEventPayload>) -> HandlerResult {
+    let (Some(before), Some(after)) = (event.before, event.after) else {
+        return Err(WebhookError::UnexpectedPayload(
+            "synchronize without before/after",
+        ));
+    };
+
+    info!("PR pushed: #{} - {before} -> {after}", event.number);
+    Ok(Some(Event::PullRequestPushed(
+        event.pull_request,
+        before,
+        after,
+    )))
+}
+
+async fn handle_pr_reviewed(event: Box<PullRequestReviewWebhookEventPayload>) -> HandlerResult {
+    let pr = event.pull_request;
+    let review = event.review;
+
+    if !is_contributor(review.author_association.as_ref()) {
+        return Ok(None);
+    }
+
+    match review.state {
+        Some(ReviewState::Approved) => {
+            info!(
+                "PR approved by {:?}: #{} - {:?} by {:?}",
+                review.clone().user.map(|user| user.login),
+                pr.number,
+                pr.clone().title,
+                pr.clone().user.map(|u| u.login)
+            );
+            Ok(Some(Event::PullRequestApproved(pr, Box::new(review))))
+        }
+        Some(ReviewState::ChangesRequested) => {
+            info!(
+                "PR changes requested by {:?}: #{} - {:?} by {:?}",
+                review.clone().user.map(|user| user.login),
+                pr.number,
+                pr.clone().title,
+                pr.clone().user.map(|u| u.login)
+            );
+            Ok(Some(Event::PullRequestChangesRequested(
+                pr,
+                Box::new(review),
+            )))
+        }
+        _ => Ok(None),
+    }
+}
+
+async fn handle_pr_review_dismissed(
+    event: Box<PullRequestReviewWebhookEventPayload>,
+) -> HandlerResult {
+    let pr = event.pull_request;
+    let review = event.review;
+
+    info!(
+        "PR review by {:?} dismissed: #{} - {:?}",
+        review.clone().user.map(|user| user.login),
+        pr.number,
+        pr.clone().title
+    );
+    Ok(Some(Event::PullRequestReviewDismissed(
+        pr,
+        Box::new(review),
+    )))
+}
+
+async fn handle_pr_event(event: WebhookEvent) -> HandlerResult {
+    let WebhookEventPayload::PullRequest(event) = event.specific else {
+        return Err(WebhookError::UnexpectedPayload("pull request"));
+    };
+
+    match event.action {
+        PullRequestWebhookEventAction::Opened => handle_pr_created(event).await,
+        PullRequestWebhookEventAction::ReadyForReview => handle_pr_ready(event).await,
+        PullRequestWebhookEventAction::Closed => handle_pr_closed(event).await,
+        PullRequestWebhookEventAction::ConvertedToDraft => handle_pr_drafted(event).await,
+        PullRequestWebhookEventAction::Reopened => handle_pr_reopened(event).await,
+        PullRequestWebhookEventAction::Edited => handle_pr_edited(event).await,
+        PullRequestWebhookEventAction::Synchronize => handle_pr_synchronized(event).await,
+        PullRequestWebhookEventAction::ReviewRequested => handle_pr_review_requested(event).await,
+        PullRequestWebhookEventAction::ReviewRequestRemoved => {
+            handle_pr_review_request_removed(event).await
+        }
+        PullRequestWebhookEventAction::Labeled | PullRequestWebhookEventAction::Unlabeled => {
+            handle_pr_labeled(event).await
+        }
+
+        _ => {
+            trace!("Ignored PR webhook event action: {:?}", event.action);
+            Ok(None)
+        }
+    }
+}
+
+async fn handle_pr_review_event(event: WebhookEvent) -> HandlerResult {
+    let WebhookEventPayload::PullRequestReview(event) = event.specific else {
+        return Err(WebhookError::UnexpectedPayload("pull request review"));
+    };
+
+    match event.action {
+        PullRequestReviewWebhookEventAction::Submitted => handle_pr_reviewed(event).await,
+        PullRequestReviewWebhookEventAction::Dismissed => handle_pr_review_dismissed(event).await,
+        _ => {
+            trace!("Ignored PR webhook event action: {:?}", event.action);
+            Ok(None)
+        }
+    }
+}
+
+async fn handle_pr_comment_event(event: WebhookEvent) -> HandlerResult {
+    let WebhookEventPayload::PullRequestReviewComment(event) = event.specific else {
+        return Err(WebhookError::UnexpectedPayload(
+            "pull request review comment",
+        ));
+    };
+
+    if event.action != PullRequestReviewCommentWebhookEventAction::Created {
+        return Ok(None);
+    }
+
+    Ok(Some(Event::PullRequestReviewComment(
+        event.pull_request.number,
+        Box::new(event.comment),
+        event.pull_request.user.map(|u| u.login),
+    )))
+}
+
+async fn handle_pr_thread_comment_event(event: WebhookEvent) -> HandlerResult {
+    let user = event
+        .sender
+        .map(|u| u.login)
+        .unwrap_or("unknown".to_string());
+    let WebhookEventPayload::PullRequestReviewThread(event) = event.specific else {
+        return Err(WebhookError::UnexpectedPayload(
+            "pull request review thread",
+        ));
+    };
+
+    // The comments themselves were already relayed, only the first one matters
+    // to reply to it
+    let Some(comment) = event.thread.comments.first() else {
+        return Err(WebhookError::UnexpectedPayload("empty review thread"));
+    };
+    let root = comment.in_reply_to_id.unwrap_or(comment.id).into_inner();
+    let resolved = match event.action {
+        PullRequestReviewThreadWebhookEventAction::Resolved => true,
+        PullRequestReviewThreadWebhookEventAction::Unresolved => false,
+        _ => {
+            trace!("Unknown PR review thread action {:?}", event.action);
+            return Ok(None);
+        }
+    };
+
+    Ok(Some(Event::PullRequestReviewThreadResolved(
+        event.pull_request.number,
+        root,
+        resolved,
+        user,
+    )))
+}
+
+// for some reason issue comments are the same as pr comments? this makes everything much more annoying
+async fn handle_issue_comment(event: WebhookEvent) -> HandlerResult {
+    let WebhookEventPayload::IssueComment(event) = event.specific else {
+        return Err(WebhookError::UnexpectedPayload("issue comment"));
+    };
+
+    if event.issue.pull_request.is_none() {
+        return Ok(None);
+    }
+    let Some(body) = event.comment.body else {
+        trace!("Ignored issue comment without body");
+        return Ok(None);
+    };
+    if relay::is_relayed(&body) {
+        trace!("Ignored issue comment relayed from Discord");
+        return Ok(None);
+    }
+
+    Ok(Some(Event::PullRequestComment(
+        event.issue.number,
+        body,
+        event.comment.user.login,
+        Some(event.issue.user.login),
+    )))
+}
+
+/// Head SHA and PR numbers of a check suite, check run or workflow run object.
+fn checks_updated(object: &serde_json::Value, what: &'static str) -> HandlerResult {
+    let Some(sha) = object["head_sha"].as_str() else {
+        return Err(WebhookError::UnexpectedPayload(what));
+    };
+    let prs = object["pull_requests"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|pr| pr["number"].as_u64())
+        .collect::<Vec<_>>();
+
+    trace!("Checks updated for {sha}: {prs:?}");
+    Ok(Some(Event::ChecksUpdated(sha.to_string(), prs)))
+}
+
+async fn handle_check_suite_event(event: WebhookEvent) -> HandlerResult {
+    let WebhookEventPayload::CheckSuite(event) = event.specific else {
+        return Err(WebhookError::UnexpectedPayload("check suite"));
+    };
+
+    match event.action {
+        CheckSuiteWebhookEventAction::Completed => {
+            checks_updated(&event.check_suite, "check suite")
+        }
+        _ => Ok(None),
+    }
+}
+
+async fn handle_check_run_event(event: WebhookEvent) -> HandlerResult {
+    let WebhookEventPayload::CheckRun(event) = event.specific else {
+        return Err(WebhookError::UnexpectedPayload("check run"));
+    };
+
+    match event.action {
+        CheckRunWebhookEventAction::Completed => checks_updated(&event.check_run, "check run"),
+        _ => Ok(None),
+    }
+}
+
+async fn handle_workflow_run_event(event: WebhookEvent) -> HandlerResult {
+    let WebhookEventPayload::WorkflowRun(event) = event.specific else {
+        return Err(WebhookError::UnexpectedPayload("workflow run"));
+    };
+
+    match event.action {
+        WorkflowRunWebhookEventAction::Requested | WorkflowRunWebhookEventAction::Completed => {
+            checks_updated(&event.workflow_run, "workflow run")
+        }
+        _ => Ok(None),
+    }
+}
+
+fn verify_signature(secret: &str, signature: &[u8], body: &[u8]) -> bool {
+    let Some(signature) = signature.strip_prefix(b"sha256=") else {
+        return false;
+    };
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+async fn read_body(body: Body) -> Result<Bytes, WebhookError> {
+    Ok(axum::body::to_bytes(body, MAX_BODY_SIZE).await?)
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &'static str) -> Result<&'a str, WebhookError> {
+    headers
+        .get(name)
+        .ok_or(WebhookError::MissingHeader(name))?
+        .to_str()
+        .map_err(|_| WebhookError::InvalidHeader(name))
+}
+
+async fn parse_event(kind: &str, body: &[u8]) -> HandlerResult {
+    let event = WebhookEvent::try_from_header_and_body(kind, body)?;
+    match event.kind {
+        WebhookEventType::PullRequest => handle_pr_event(event).await,
+        WebhookEventType::PullRequestReview => handle_pr_review_event(event).await,
+        WebhookEventType::PullRequestReviewComment => handle_pr_comment_event(event).await,
+        WebhookEventType::PullRequestReviewThread => handle_pr_thread_comment_event(event).await,
+        WebhookEventType::IssueComment => handle_issue_comment(event).await,
+        WebhookEventType::CheckSuite => handle_check_suite_event(event).await,
+        WebhookEventType::CheckRun => handle_check_run_event(event).await,
+        WebhookEventType::WorkflowRun => handle_workflow_run_event(event).await,
+
+        _ => {
+            trace!("Webhook event of kind {:?}", event.kind);
+            Ok(None)
+        }
+    }
+}
+
+async fn handle_delivery(
+    state: &WebhookState,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<StatusCode, WebhookError> {
+    let id = header(headers, "X-GitHub-Delivery")?;
+    let kind = header(headers, "X-GitHub-Event")?;
+
+    if !state.deliveries.begin(id, kind).await {
+        info!("Dropped duplicate webhook delivery {id}");
+        return Ok(StatusCode::OK);
+    }
+
+    let event = match parse_event(kind, body).await {
+        Ok(event) => event,
+        Err(err) => {
+            state.deliveries.finish(id, None, Err(&err)).await;
+            return Err(err);
+        }
+    };
+
+    let result = match &event {
+        Some(event) => send_event(event.clone()).map_err(WebhookError::from),
+        None => Ok(()),
+    };
+    state
+        .deliveries
+        .finish(id, event.as_ref(), result.as_ref().map(|_| ()))
+        .await;
+
+    result.map(|_| StatusCode::OK)
+}
+
+async fn push(
+    State(state): State<Arc<WebhookState>>,
+    request: Request<Body>,
+) -> Result<StatusCode, WebhookError> {
+    let (parts, body) = request.into_parts();
+    // Checked before buffering so unsigned requests are turned away cheaply
+    let signature = parts
+        .headers
+        .get("X-Hub-Signature-256")
+        .ok_or(WebhookError::MissingSignature)?;
+    let body = read_body(body).await?;
+
+    if !verify_signature(&state.secret, signature.as_bytes(), &body) {
+        return Err(WebhookError::InvalidSignature);
+    }
+
+    handle_delivery(&state, &parts.headers, &body).await
+}
+
+/// Old `/push/{secret}` route, only registered when `WEBHOOK_LEGACY_PATH_SECRET=true`.
+async fn push_legacy(
+    Path(actual_secret): Path<String>,
+    State(state): State<Arc<WebhookState>>,
+    request: Request<Body>,
+) -> Result<StatusCode, WebhookError> {
+    if !bool::from(actual_secret.as_bytes().ct_eq(state.secret.as_bytes())) {
+        return Err(WebhookError::IncorrectSecret);
+    }
+
+    let (parts, body) = request.into_parts();
+    let body = read_body(body).await?;
+
+    handle_delivery(&state, &parts.headers, &body).await
+}
+
+async fn list_deliveries(State(state): State<Arc<WebhookState>>) -> Json<Vec<Delivery>> {
+    Json(state.deliveries.recent())
+}
+
+pub async fn setup_webhook() {
+    let Ok(secret) = env::var("WEBHOOK_SECRET") else {
+        error!("Missing env var WEBHOOK_SECRET - webhook will not work.");
+        return;
+    };
+    let legacy = env::var("WEBHOOK_LEGACY_PATH_SECRET").is_ok_and(|v| v == "true");
+    let admin_addr = env::var("ADMIN_ADDR").unwrap_or("127.0.0.1:8081".to_string());
+
+    let state = Arc::new(WebhookState {
+        secret,
+        deliveries: DeliveryStore::load(state_path("DELIVERIES_FILE", "deliveries.json")),
+    });
+
+    let mut app = Router::new().route("/push", post(push));
+    if legacy {
+        warn!(
+            "Legacy path secret webhook route enabled - deliveries on it are not signature checked"
+        );
+        app = app.route("/push/{actual_secret}", post(push_legacy));
+    }
+    let app = app.with_state(state.clone());
+
+    // Only meant to be reachable locally, so it gets its own listener
+    let admin = Router::new()
+        .route("/deliveries", get(list_deliveries))
+        .with_state(state);
+    let admin_listener = TcpListener::bind(&admin_addr).await.unwrap();
+    tokio::spawn(async move { axum::serve(admin_listener, admin).await.unwrap() });
+
+    let listener = TcpListener::bind("0.0.0.0:8080").await.unwrap();
+    axum::serve(listener, app).await.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "It's a Secret to Everybody";
+    const BODY: &[u8] = b"Hello, World!";
+
+    fn sign(secret: &str, body: &[u8]) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body);
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes())).into_bytes()
+    }
+
+    #[test]
+    fn accepts_valid_signature() {
+        assert!(verify_signature(SECRET, &sign(SECRET, BODY), BODY));
+    }
+
+    #[test]
+    fn accepts_github_example_signature() {
+        // From GitHub's "Validating webhook deliveries" docs
+        let signature = b"sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+        assert!(verify_signature(SECRET, signature, BODY));
+    }
+
+    #[test]
+    fn rejects_mismatched_signature() {
+        assert!(!verify_signature(SECRET, &sign("wrong secret", BODY), BODY));
+        assert!(!verify_signature(
+            SECRET,
+            &sign(SECRET, BODY),
+            b"Hello, World?"
+        ));
+    }
+
+    #[test]
+    fn rejects_missing_prefix() {
+        let signature = sign(SECRET, BODY);
+        assert!(!verify_signature(SECRET, &signature[7..], BODY));
+        let sha1 = [b"sha1=".as_slice(), &signature[7..]].concat();
+        assert!(!verify_signature(SECRET, &sha1, BODY));
+    }
+
+    #[test]
+    fn rejects_bad_hex() {
+        assert!(!verify_signature(SECRET, b"sha256=not hex at all", BODY));
+        assert!(!verify_signature(SECRET, b"sha256=abc", BODY));
+        assert!(!verify_signature(SECRET, b"sha256=", BODY));
+    }
+}